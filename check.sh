@@ -0,0 +1,18 @@
+#!/bin/sh
+# Runs the tests of every plugin, with every combination of its features,
+# the contract tests included. Stops at the first failure.
+set -e
+cd "$(dirname "$0")"
+
+run() {
+    echo "== $1 $2"
+    (cd "$1" && cargo test $2)
+}
+
+run tools
+run soundcloud
+run generic
+run youtube
+run youtube "--features color"
+run youtube "--features waveform"
+run youtube --all-features
@@ -0,0 +1,122 @@
+//! Contract every plugin of this repository fulfils towards phosphorus_core.
+//!
+//! Each plugin runs these checks from its own tests, against its plugin type
+//! and its fixtures, so a change of the core API, or of a plugin, that breaks
+//! the expectations of the plugin manager fails the tests of every plugin
+//! affected. Searches are answered with the fixtures of the plugin, and what
+//! it parses is compared to snapshots checked in along with them. `check.sh`
+//! runs them for every plugin and feature combination.
+
+use phosphorus_core::plugin_manager::{plugin::Plugin, query::QueryInfo};
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+/// Checks what the plugin manager expects of `plugin` before querying it:
+/// an https base url a request can be built for, and a downloader and a
+/// streamer.
+pub fn check_plugin(plugin: &dyn Plugin) {
+    let base_url = plugin.base_url();
+    match reqwest::Url::parse(base_url) {
+        Ok(url) => assert_eq!(url.scheme(), "https", "Base url {}", base_url),
+        Err(err) => panic!("Base url {} doesn't parse: {}", base_url, err),
+    }
+
+    let method = plugin.method();
+    assert!(
+        method == reqwest::Method::GET || method == reqwest::Method::POST,
+        "Unexpected method {}",
+        method
+    );
+    if let Err(err) = reqwest::Client::new().request(method, base_url).build() {
+        panic!("No request can be built for {}: {}", base_url, err);
+    }
+
+    let _ = plugin.download();
+    let _ = plugin.stream();
+}
+
+/// Returns the `.json` fixtures in `dir`, in alphabetical order
+pub fn fixtures(dir: &Path) -> Vec<PathBuf> {
+    let mut fixtures: Vec<_> = fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("No fixtures in {}: {}", dir.display(), err))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("json"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "No fixtures in {}", dir.display());
+
+    fixtures
+}
+
+/// Searches `query` like the plugin manager does: the request built by
+/// `Plugin::query` is answered with each fixture, that `Plugin::parse` reads.
+/// Fixtures named `*-error*` are answers to failed searches and must be
+/// refused, the others must have results. No fixture may panic.
+///
+/// The outcome of each fixture is compared to the snapshot named after it in
+/// `snapshots`. Run the tests with `UPDATE_SNAPSHOTS=1` to write the
+/// snapshots again, after a change of the parsing.
+pub fn check_search(plugin: &dyn Plugin, query: &str, fixtures: &[PathBuf], snapshots: &Path) {
+    let info = QueryInfo::new(query);
+    let base_url = reqwest::Url::parse(plugin.base_url()).unwrap();
+    let request = plugin
+        .query(
+            &info,
+            reqwest::Client::new().request(plugin.method(), base_url.clone()),
+        )
+        .unwrap_or_else(|err| panic!("No request for `{}`: {}", query, err));
+    assert_eq!(request.method(), plugin.method());
+    assert_eq!(request.url().host_str(), base_url.host_str());
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    for fixture in fixtures {
+        let name = fixture.file_stem().unwrap().to_string_lossy();
+        let body = fs::read_to_string(fixture).unwrap();
+        let resp = http::Response::builder()
+            .status(200)
+            .header("content-type", "application/json")
+            .body(body)
+            .unwrap();
+
+        let parsed = panic::catch_unwind(AssertUnwindSafe(|| {
+            runtime.block_on(plugin.parse(&info, reqwest::Response::from(resp)))
+        }))
+        .unwrap_or_else(|_| panic!("Parsing fixture `{}` panicked", name));
+        let outcome = match parsed {
+            Ok(_) if name.contains("-error") => panic!("Fixture `{}` was accepted", name),
+            Err(err) if !name.contains("-error") => panic!("Fixture `{}`: {}", name, err),
+            Ok(result) if result.items.is_empty() => panic!("Fixture `{}` has no result", name),
+            Ok(result) => format!("{:#?}\n", result.items),
+            Err(err) => format!("Error: {}\n", err),
+        };
+        check_snapshot(&snapshots.join(format!("{}.snap", name)), &outcome);
+    }
+}
+
+fn check_snapshot(path: &Path, outcome: &str) {
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, outcome).unwrap();
+        return;
+    }
+
+    match fs::read_to_string(path) {
+        Ok(snapshot) => assert!(
+            snapshot == outcome,
+            "Outcome differs from the snapshot {}, run with UPDATE_SNAPSHOTS=1 if expected:\n{}",
+            path.display(),
+            outcome
+        ),
+        Err(err) => panic!(
+            "No snapshot {} ({}), run with UPDATE_SNAPSHOTS=1 to write it",
+            path.display(),
+            err
+        ),
+    }
+}
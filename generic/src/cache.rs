@@ -0,0 +1,140 @@
+//! Disk cache of search answers, revalidated with `ETag` and `Last-Modified`.
+//!
+//! Browsing a catalog sends the same searches again and again. Once
+//! [`set_dir`] is called, answers carrying validators are stored in that
+//! directory, and the following identical `GET` searches send `If-None-Match` and
+//! `If-Modified-Since`: a `304 Not Modified` answer is parsed from the stored
+//! body, which spares the source. The cache is disabled by default.
+//!
+//! Entries are named after a hash of the url the search is sent to, before
+//! any redirect. The url holds the credentials of the source, so it isn't
+//! written itself.
+
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+static DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Stores answers in `dir`, or disables the cache with `None`, the default
+pub fn set_dir(dir: Option<PathBuf>) {
+    *DIR.write().unwrap() = dir;
+}
+
+/// Returns the directory answers are stored in, if the cache is enabled
+pub fn dir() -> Option<PathBuf> {
+    DIR.read().unwrap().clone()
+}
+
+/// Stored answer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// Adds to `request` the validators of the answer stored for `url`, if any
+pub(crate) fn revalidate(url: &reqwest::Url, request: &mut reqwest::Request) {
+    if let Some(dir) = dir() {
+        revalidate_in(&dir, url, request);
+    }
+}
+
+/// Returns the answer stored for `url`
+pub(crate) fn load(url: &reqwest::Url) -> Option<Entry> {
+    load_from(&dir()?, url)
+}
+
+/// Stores `body`, the answer for `url`, if `headers` hold validators
+pub(crate) fn store(url: &reqwest::Url, headers: &HeaderMap, body: &str) {
+    if let Some(dir) = dir() {
+        store_in(&dir, url, headers, body);
+    }
+}
+
+fn revalidate_in(dir: &Path, url: &reqwest::Url, request: &mut reqwest::Request) {
+    let entry = match load_from(dir, url) {
+        Some(entry) => entry,
+        None => return,
+    };
+    let headers = request.headers_mut();
+    if let Some(Ok(etag)) = entry.etag.map(|etag| etag.parse()) {
+        headers.insert(IF_NONE_MATCH, etag);
+    }
+    if let Some(Ok(date)) = entry.last_modified.map(|date| date.parse()) {
+        headers.insert(IF_MODIFIED_SINCE, date);
+    }
+}
+
+fn load_from(dir: &Path, url: &reqwest::Url) -> Option<Entry> {
+    let text = fs::read_to_string(entry_path(dir, url)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+fn store_in(dir: &Path, url: &reqwest::Url, headers: &HeaderMap, body: &str) {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let entry = Entry {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+        body: body.into(),
+    };
+    if entry.etag.is_none() && entry.last_modified.is_none() {
+        return;
+    }
+
+    // The cache is best effort, an answer that can't be stored is fetched again
+    if let Ok(text) = serde_json::to_string(&entry) {
+        let _ = fs::create_dir_all(dir).and_then(|_| fs::write(entry_path(dir, url), text));
+    }
+}
+
+/// Path of the entry of `url`, named after its FNV-1a hash
+fn entry_path(dir: &Path, url: &reqwest::Url) -> PathBuf {
+    let hash = url
+        .as_str()
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    dir.join(format!("{:016x}.json", hash))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_from, revalidate_in, store_in};
+    use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH};
+
+    #[test]
+    fn validators_are_sent_back() {
+        let dir = std::env::temp_dir().join(format!("phosphorus-cache-{}", std::process::id()));
+        let url = reqwest::Url::parse("https://api.jamendo.com/v3.0/tracks/?search=ketsa").unwrap();
+
+        store_in(&dir, &url, &HeaderMap::new(), "{}");
+        assert_eq!(load_from(&dir, &url), None, "Answers without validators");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "\"5d8c72a5\"".parse().unwrap());
+        store_in(&dir, &url, &headers, r#"{"results": []}"#);
+        assert_eq!(load_from(&dir, &url).unwrap().body, r#"{"results": []}"#);
+
+        let mut revalidated = reqwest::Client::new().get(url.clone()).build().unwrap();
+        revalidate_in(&dir, &url, &mut revalidated);
+        assert_eq!(revalidated.headers()[IF_NONE_MATCH], "\"5d8c72a5\"");
+        assert!(revalidated.headers().get(IF_MODIFIED_SINCE).is_none());
+
+        let other =
+            reqwest::Url::parse("https://api.jamendo.com/v3.0/tracks/?search=other").unwrap();
+        assert_eq!(load_from(&dir, &other), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,20 @@
+//! Contract tests towards phosphorus_core, see `plugin_contract`.
+
+use crate::{Descriptor, Generic};
+use std::path::Path;
+
+#[test]
+fn plugin_contract() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    for loaded in Generic::load_dir(&root.join("sources")).unwrap() {
+        plugin_contract::check_plugin(&loaded.unwrap());
+    }
+
+    let jamendo = Generic::new(Descriptor::load(&root.join("sources/jamendo.toml")).unwrap());
+    plugin_contract::check_search(
+        &jamendo,
+        "wanderer",
+        &plugin_contract::fixtures(&root.join("tests/corpus")),
+        &root.join("tests/snapshots"),
+    );
+}
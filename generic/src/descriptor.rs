@@ -0,0 +1,224 @@
+//! Data files describing a source.
+//!
+//! A descriptor tells where to search, how to authenticate, and where every
+//! field of a result is in the JSON answer. Fields are located with JSON
+//! pointers (RFC 6901), such as `/user/username`, relative to a result.
+//! Pointers aren't JSONPath: they locate a single value, without wildcards,
+//! filters or recursive descent. `$.user.username` is written
+//! `/user/username`, `$.images[0]` is written `/images/0`, and keys holding
+//! `/` or `~` escape them as `~1` and `~0`. JSONPath expressions are refused.
+//! Descriptors are written in TOML or JSON, see `sources/` for an example.
+
+use serde::Deserialize;
+use std::{collections::BTreeMap, error::Error, fmt, fs, path::Path};
+
+/// Placeholder replaced by the text searched
+pub const QUERY_PLACEHOLDER: &'static str = "{query}";
+
+/// A source, as read from its data file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Descriptor {
+    /// Name shown to the user
+    pub name: String,
+    pub search: Search,
+    #[serde(default)]
+    pub auth: Auth,
+    pub fields: Fields,
+}
+
+/// The search endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Search {
+    pub url: String,
+    #[serde(default)]
+    pub method: Method,
+    /// Parameters of the search, sent in the query string for `GET` and as a
+    /// form for `POST`. Values can contain `{query}`.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// Pointer to the array of results in the answer, empty if the answer is
+    /// the array itself
+    #[serde(default)]
+    pub results: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    #[default]
+    Get,
+    Post,
+}
+
+/// How requests are authenticated
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(tag = "scheme", rename_all = "snake_case")]
+pub enum Auth {
+    #[default]
+    None,
+    /// A key sent in the query string, such as `api_key=...`
+    QueryParam { name: String, value: String },
+    /// A key sent in a header, such as `X-Api-Key: ...`
+    Header { name: String, value: String },
+    /// A token sent as `Authorization: Bearer ...`
+    Bearer { token: String },
+}
+
+/// Pointers to the fields of a result
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Fields {
+    pub id: String,
+    pub title: String,
+    /// Page or media file of the track, downloaded with yt-dlp
+    pub url: String,
+    pub thumbnail: String,
+    pub artist: String,
+    /// The track thumbnail is used if missing
+    pub artist_thumbnail: Option<String>,
+    pub duration: String,
+    /// License of the track, such as the url of a Creative Commons license.
+    /// Results don't hold it, see `license`.
+    pub license: Option<String>,
+    #[serde(default)]
+    pub duration_unit: DurationUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+}
+
+/// A descriptor that can't be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDescriptor(pub String);
+
+impl fmt::Display for InvalidDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid source descriptor: {}", self.0)
+    }
+}
+
+impl Error for InvalidDescriptor {}
+
+impl Descriptor {
+    /// Reads and validates the descriptor at `path`, in TOML unless its
+    /// extension is `.json`
+    pub fn load(path: &Path) -> Result<Descriptor, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let descriptor = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Descriptor::from_json(&text),
+            _ => Descriptor::from_toml(&text),
+        };
+
+        descriptor.map_err(|err| format!("{}: {}", path.display(), err).into())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Descriptor, Box<dyn Error>> {
+        let descriptor: Descriptor = toml::from_str(text)?;
+        descriptor.validate()?;
+        Ok(descriptor)
+    }
+
+    pub fn from_json(text: &str) -> Result<Descriptor, Box<dyn Error>> {
+        let descriptor: Descriptor = serde_json::from_str(text)?;
+        descriptor.validate()?;
+        Ok(descriptor)
+    }
+
+    fn validate(&self) -> Result<(), InvalidDescriptor> {
+        if self.name.trim().is_empty() {
+            return Err(InvalidDescriptor("`name` is empty".into()));
+        }
+        match reqwest::Url::parse(&self.search.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => (),
+            _ => {
+                return Err(InvalidDescriptor(format!(
+                    "`search.url` {} isn't an http url",
+                    self.search.url
+                )))
+            }
+        }
+        if !self
+            .search
+            .params
+            .values()
+            .any(|value| value.contains(QUERY_PLACEHOLDER))
+        {
+            return Err(InvalidDescriptor(format!(
+                "No parameter of `search.params` contains {}",
+                QUERY_PLACEHOLDER
+            )));
+        }
+
+        let fields = &self.fields;
+        let pointers = [
+            ("search.results", Some(&self.search.results)),
+            ("fields.id", Some(&fields.id)),
+            ("fields.title", Some(&fields.title)),
+            ("fields.url", Some(&fields.url)),
+            ("fields.thumbnail", Some(&fields.thumbnail)),
+            ("fields.artist", Some(&fields.artist)),
+            ("fields.artist_thumbnail", fields.artist_thumbnail.as_ref()),
+            ("fields.duration", Some(&fields.duration)),
+            ("fields.license", fields.license.as_ref()),
+        ];
+        for (name, pointer) in pointers {
+            match pointer {
+                Some(pointer) if pointer.starts_with('$') => {
+                    return Err(InvalidDescriptor(format!(
+                        "`{}` {} is JSONPath, write it as a JSON pointer such as `/a/0/b`",
+                        name, pointer
+                    )))
+                }
+                Some(pointer) if !pointer.is_empty() && !pointer.starts_with('/') => {
+                    return Err(InvalidDescriptor(format!(
+                        "`{}` {} isn't a JSON pointer",
+                        name, pointer
+                    )))
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Auth, Descriptor, DurationUnit, Method};
+
+    #[test]
+    fn descriptor_validation() {
+        let descriptor = Descriptor::from_toml(include_str!("../sources/jamendo.toml")).unwrap();
+        assert_eq!(descriptor.search.method, Method::Get);
+        assert_eq!(descriptor.fields.duration_unit, DurationUnit::Seconds);
+        assert!(matches!(descriptor.auth, Auth::QueryParam { .. }));
+
+        let json = r#"{
+            "name": "Example",
+            "search": { "url": "https://example.com/search", "params": { "q": "{query}" } },
+            "auth": { "scheme": "bearer", "token": "secret" },
+            "fields": {
+                "id": "/id", "title": "/title", "url": "/url", "thumbnail": "/cover",
+                "artist": "/artist", "duration": "/length", "duration_unit": "milliseconds"
+            }
+        }"#;
+        assert!(Descriptor::from_json(json).is_ok());
+
+        let no_query = json.replace("{query}", "music");
+        assert!(Descriptor::from_json(&no_query).is_err());
+        let not_a_pointer = json.replace("\"/title\"", "\"title\"");
+        assert!(Descriptor::from_json(&not_a_pointer).is_err());
+        let json_path = json.replace("\"/title\"", "\"$.title\"");
+        assert!(Descriptor::from_json(&json_path)
+            .unwrap_err()
+            .to_string()
+            .contains("JSONPath"));
+        let not_http = json.replace("https://", "ftp://");
+        assert!(Descriptor::from_json(&not_http).is_err());
+    }
+}
@@ -0,0 +1,475 @@
+//! Plugins for simple REST sources, described by data files.
+//!
+//! Every [`Descriptor`] becomes a [`Generic`] plugin: searches are sent to
+//! the endpoint it describes, and results are read from the fields it points
+//! to. Tracks are downloaded and streamed with yt-dlp, so the url of a result
+//! has to be a page yt-dlp understands, or a media file. Answers can be kept
+//! on disk and revalidated, see [`cache`].
+//!
+//! Results have no license field, so the license of a track, for sources
+//! describing one, is returned by [`license`] and written to the tags of
+//! its downloads. The host can't filter results by license with it: only
+//! the licenses of the last [`MAX_LICENSES`] tracks parsed are kept.
+
+use async_trait::async_trait;
+use descriptor::{Auth, DurationUnit, Method, QUERY_PLACEHOLDER};
+use phosphorus_core::plugin_manager::{
+    downloader::*,
+    error::ParseError,
+    plugin::Plugin,
+    query::*,
+    streamer::{StreamBuilder, Streamer},
+};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    error::Error,
+    fs,
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+
+pub mod cache;
+#[cfg(test)]
+mod contract;
+pub mod descriptor;
+
+pub use descriptor::Descriptor;
+
+/// A plugin, or the reason its descriptor couldn't be loaded
+pub type Loaded = Result<Generic, Box<dyn Error>>;
+
+/// Base urls of the plugins created so far, see `intern`
+static BASE_URLS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+/// Licenses of the tracks parsed last, by track url
+static LICENSES: Mutex<Licenses> = Mutex::new(Licenses::new(MAX_LICENSES));
+
+/// Licenses kept, the oldest is forgotten first
+pub const MAX_LICENSES: usize = 1000;
+
+/// Returns the license of the track at `url`, if its source describes one and
+/// the track was among the results of a recent search
+pub fn license(url: &str) -> Option<String> {
+    LICENSES.lock().unwrap().get(url).cloned()
+}
+
+/// Licenses of the last `capacity` tracks parsed
+struct Licenses {
+    capacity: usize,
+    licenses: BTreeMap<String, String>,
+    /// Track urls, least recently parsed first
+    order: VecDeque<String>,
+}
+
+impl Licenses {
+    const fn new(capacity: usize) -> Self {
+        Licenses {
+            capacity,
+            licenses: BTreeMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, url: &str) -> Option<&String> {
+        self.licenses.get(url)
+    }
+
+    fn insert(&mut self, url: String, license: String) {
+        if self.licenses.insert(url.clone(), license).is_some() {
+            self.order.retain(|parsed| *parsed != url);
+        }
+        self.order.push_back(url);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.licenses.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Plugin searching the source described by its descriptor
+pub struct Generic {
+    descriptor: Descriptor,
+    /// `Plugin::base_url` lives as long as the plugin manager
+    base_url: &'static str,
+}
+
+impl Generic {
+    pub fn new(descriptor: Descriptor) -> Self {
+        let base_url = intern(&descriptor.search.url);
+        Generic {
+            descriptor,
+            base_url,
+        }
+    }
+
+    /// Returns the descriptor of the source
+    pub fn descriptor(&self) -> &Descriptor {
+        &self.descriptor
+    }
+
+    /// Returns the parameters of the search for `query`
+    fn params(&self, query: &str) -> Vec<(&str, String)> {
+        let mut params: Vec<(&str, String)> = self
+            .descriptor
+            .search
+            .params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.replace(QUERY_PLACEHOLDER, query)))
+            .collect();
+        if let Auth::QueryParam { name, value } = &self.descriptor.auth {
+            params.push((name, value.clone()));
+        }
+        params
+    }
+
+    /// Returns the url the answer to the search for `query` is cached under,
+    /// `None` if it isn't cached. Only `GET` searches are, by the url they
+    /// are sent to: the response only knows the url it was redirected to.
+    fn cached_url(&self, query: &str) -> Result<Option<reqwest::Url>, Box<dyn Error>> {
+        if self.descriptor.search.method != Method::Get {
+            return Ok(None);
+        }
+        Ok(Some(reqwest::Url::parse_with_params(
+            self.base_url,
+            &self.params(query),
+        )?))
+    }
+
+    /// Loads every `.toml` and `.json` descriptor in `dir`, in alphabetical
+    /// order. A descriptor that can't be used doesn't prevent the others from
+    /// loading: its error, naming the file, is returned in its place.
+    pub fn load_dir(dir: &Path) -> Result<Vec<Loaded>, Box<dyn Error>> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("toml" | "json")
+            ) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        Ok(paths
+            .iter()
+            .map(|path| Descriptor::load(path).map(Generic::new))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Plugin for Generic {
+    fn method(&self) -> reqwest::Method {
+        match self.descriptor.search.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+        }
+    }
+
+    fn base_url(&self) -> &'static str {
+        self.base_url
+    }
+
+    fn query(
+        &self,
+        info: &QueryInfo,
+        mut req_builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Request, Box<dyn Error>> {
+        let search = &self.descriptor.search;
+        let params = self.params(info.raw());
+        match &self.descriptor.auth {
+            Auth::Header { name, value } => req_builder = req_builder.header(name, value),
+            Auth::Bearer { token } => req_builder = req_builder.bearer_auth(token),
+            Auth::None | Auth::QueryParam { .. } => (),
+        }
+        req_builder = match search.method {
+            Method::Get => req_builder.query(&params),
+            Method::Post => req_builder.form(&params),
+        };
+        let mut request = req_builder.build()?;
+        if let Some(url) = self.cached_url(info.raw())? {
+            cache::revalidate(&url, &mut request);
+        }
+        Ok(request)
+    }
+
+    async fn parse(
+        &self,
+        info: &QueryInfo,
+        resp: reqwest::Response,
+    ) -> Result<QueryResult, Box<dyn Error>> {
+        let url = self.cached_url(info.raw())?;
+        let text = if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            match url.as_ref().and_then(cache::load) {
+                Some(entry) => entry.body,
+                None => return Err(Box::new(ParseError::InvalidResponseText)),
+            }
+        } else if resp.status().is_success() {
+            let headers = resp.headers().clone();
+            let text = resp.text().await?;
+            if let Some(url) = url {
+                cache::store(&url, &headers, &text);
+            }
+            text
+        } else {
+            return Err(Box::new(ParseError::InvalidResponseText));
+        };
+        let json: Value = match serde_json::from_str(&text) {
+            Ok(json) => json,
+            Err(cause) => return Err(Box::new(ParseError::JsonUnparsable(cause.to_string()))),
+        };
+
+        Ok(QueryResult::new(parse_results(&self.descriptor, &json)?))
+    }
+
+    fn download(&self) -> Downloader {
+        DownloadBuilder::new(download_fn)
+    }
+
+    fn stream(&self) -> Streamer {
+        StreamBuilder::new(stream_fn)
+    }
+}
+
+/// Builds the command downloading the media at `url`, see `download_fn`
+fn download_command(url: &str, file_name: &str) -> Command {
+    let mut download_command = Command::new("yt-dlp");
+    download_command.args(["--extract-audio", "--audio-format", "mp3"]);
+    if let Some(license) = license(url) {
+        download_command.args([
+            "--embed-metadata",
+            "--postprocessor-args",
+            &format!("FFmpegMetadata:-metadata license={}", license),
+        ]);
+    }
+    let output_file = format!("{}.%(ext)s", file_name);
+    download_command.args(["-o", &output_file]);
+    download_command.arg(url);
+
+    download_command
+}
+
+/// Returns `url` with a static lifetime, as `Plugin::base_url` requires. Each
+/// url is leaked once, however many plugins are created for it.
+fn intern(url: &str) -> &'static str {
+    let mut base_urls = BASE_URLS.lock().unwrap();
+    match base_urls.iter().find(|base_url| **base_url == url) {
+        Some(base_url) => base_url,
+        None => {
+            let base_url = Box::leak(url.to_string().into_boxed_str());
+            base_urls.push(base_url);
+            base_url
+        }
+    }
+}
+
+/// Creates a sub-process that downloads the media associated to `url`. That media is than
+/// saved as `file_name.[ext]`. The handler to the sub-process is returned.
+pub fn download_fn(url: &str, file_name: &str) -> Result<Child, Box<dyn Error>> {
+    let mut download_command = download_command(url, file_name);
+    let handler = download_command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    Ok(handler)
+}
+
+pub fn stream_fn(url: &str, file_name: &str) -> Result<std::process::Child, Box<dyn Error>> {
+    let output = Command::new("yt-dlp")
+        .args(["-g", "-f", "bestaudio/best", url])
+        .output()?;
+    let audio_url = String::from_utf8(output.stdout)?;
+    let audio_url = match audio_url.lines().next() {
+        Some(audio_url) if output.status.success() => audio_url.to_string(),
+        _ => return Err(Box::new(ParseError::ParsableTextNotFound)),
+    };
+
+    let mut stream_getter = Command::new("ffmpeg");
+    stream_getter.args(["-i", &audio_url]);
+    stream_getter.args(["-c:a", "libmp3lame"]);
+    let file = format!("file:{}", file_name);
+    stream_getter.arg(&file);
+    stream_getter.arg("-y"); // If `file_name` exists, it's overwritten
+
+    let handler = stream_getter
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    Ok(handler)
+}
+
+/// Parses the results of a search answered with `json`
+fn parse_results(
+    descriptor: &Descriptor,
+    json: &Value,
+) -> Result<Vec<QueryResultData>, Box<dyn Error>> {
+    let results = match read(json, &descriptor.search.results)?.as_array() {
+        Some(results) => results,
+        None => return Err(Box::new(ParseError::InvalidResponseText)),
+    };
+    let mut items = vec![];
+    for result in results {
+        items.push(parse_result(descriptor, result)?);
+    }
+
+    Ok(items)
+}
+
+fn parse_result(
+    descriptor: &Descriptor,
+    source: &Value,
+) -> Result<QueryResultData, Box<dyn Error>> {
+    let fields = &descriptor.fields;
+    let track_thumbnail = read_text(source, &fields.thumbnail)?;
+    let artist_thumbnail = match &fields.artist_thumbnail {
+        Some(pointer) => read_text(source, pointer)?,
+        None => track_thumbnail.clone(),
+    };
+
+    let length = match read_text(source, &fields.duration)?.parse::<f64>() {
+        Ok(length) if length.is_finite() && length >= 0.0 => length,
+        _ => {
+            return Err(Box::new(ParseError::JsonUnparsable(format!(
+                "Field `{}` isn't a duration",
+                fields.duration
+            ))))
+        }
+    };
+    let duration = match fields.duration_unit {
+        DurationUnit::Seconds => Duration::from_secs_f64(length),
+        DurationUnit::Milliseconds => Duration::from_secs_f64(length / 1000.0),
+    };
+
+    let url = read_text(source, &fields.url)?;
+    let result = QueryResultData::new(
+        &read_text(source, &fields.id)?,
+        &read_text(source, &fields.title)?,
+        reqwest::Url::parse(&url)?,
+        reqwest::Url::parse(&track_thumbnail)?,
+        &read_text(source, &fields.artist)?,
+        reqwest::Url::parse(&artist_thumbnail)?,
+        duration,
+    );
+
+    // Optional, like its field. Kept once the result is known to be valid.
+    let license = match &fields.license {
+        Some(pointer) => read_text(source, pointer).ok(),
+        None => None,
+    };
+    if let Some(license) = license {
+        LICENSES.lock().unwrap().insert(url, license);
+    }
+
+    Ok(result)
+}
+
+/// Utility to retrieve the `Value` at `pointer`
+fn read<'a>(source: &'a Value, pointer: &str) -> Result<&'a Value, Box<dyn Error>> {
+    match source.pointer(pointer) {
+        Some(value) => Ok(value),
+        None => Err(Box::new(ParseError::JsonUnparsable(format!(
+            "Missing field `{}`",
+            pointer
+        )))),
+    }
+}
+
+/// Utility to retrieve the string or the number at `pointer` as text
+fn read_text(source: &Value, pointer: &str) -> Result<String, Box<dyn Error>> {
+    match read(source, pointer)? {
+        Value::String(text) => Ok(text.clone()),
+        Value::Number(number) => Ok(number.to_string()),
+        _ => Err(Box::new(ParseError::JsonUnparsable(format!(
+            "Field `{}` isn't a string",
+            pointer
+        )))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        descriptor::Method, download_command, intern, license, parse_results, Descriptor, Generic,
+        Licenses,
+    };
+
+    #[test]
+    fn results_parsing() {
+        let descriptor = Descriptor::from_toml(include_str!("../sources/jamendo.toml")).unwrap();
+        let json = serde_json::json!({
+            "headers": { "status": "success" },
+            "results": [{
+                "id": "1886257",
+                "name": "Wanderer",
+                "duration": 214,
+                "artist_name": "Ketsa",
+                "image": "https://usercontent.jamendo.com?type=album&id=452066&width=300",
+                "shareurl": "https://www.jamendo.com/track/1886257",
+                "license_ccurl": "http://creativecommons.org/licenses/by-nc-nd/3.0/"
+            }]
+        });
+        assert_eq!(parse_results(&descriptor, &json).unwrap().len(), 1);
+        assert_eq!(
+            license("https://www.jamendo.com/track/1886257").as_deref(),
+            Some("http://creativecommons.org/licenses/by-nc-nd/3.0/")
+        );
+        let command = download_command("https://www.jamendo.com/track/1886257", "out");
+        assert!(command.get_args().any(|arg| {
+            arg
+            == "FFmpegMetadata:-metadata license=http://creativecommons.org/licenses/by-nc-nd/3.0/"
+        }));
+
+        let mut no_title = json.clone();
+        no_title["results"][0]["name"] = serde_json::Value::Null;
+        no_title["results"][0]["shareurl"] = "https://www.jamendo.com/track/1".into();
+        assert!(parse_results(&descriptor, &no_title).is_err());
+        assert_eq!(
+            license("https://www.jamendo.com/track/1"),
+            None,
+            "Invalid results don't keep their license"
+        );
+        assert!(parse_results(&descriptor, &json["headers"]).is_err());
+        assert!(std::ptr::eq(
+            intern(&descriptor.search.url),
+            intern("https://api.jamendo.com/v3.0/tracks/")
+        ));
+    }
+
+    #[test]
+    fn oldest_licenses_are_forgotten() {
+        let mut licenses = Licenses::new(2);
+        licenses.insert("a".into(), "by".into());
+        licenses.insert("b".into(), "by-sa".into());
+        licenses.insert("a".into(), "by-nc".into());
+        licenses.insert("c".into(), "by-nd".into());
+
+        assert_eq!(licenses.get("a").map(String::as_str), Some("by-nc"));
+        assert_eq!(licenses.get("b"), None, "Parsed least recently");
+        assert_eq!(licenses.get("c").map(String::as_str), Some("by-nd"));
+    }
+
+    #[test]
+    fn searches_are_cached_by_their_url() {
+        let mut descriptor =
+            Descriptor::from_toml(include_str!("../sources/jamendo.toml")).unwrap();
+        let plugin = Generic::new(descriptor.clone());
+        let request = reqwest::Client::new()
+            .get(plugin.base_url)
+            .query(&plugin.params("ketsa"))
+            .build()
+            .unwrap();
+        assert_eq!(
+            plugin.cached_url("ketsa").unwrap().as_ref(),
+            Some(request.url())
+        );
+
+        descriptor.search.method = Method::Post;
+        assert_eq!(Generic::new(descriptor).cached_url("ketsa").unwrap(), None);
+    }
+}
@@ -0,0 +1,14 @@
+//! Paths of the binaries the plugin runs, `yt-dlp` and `ffmpeg`.
+//!
+//! They're shared with the other plugins, see `plugin_tools::binaries`. The
+//! host should call [`probe`] when loading the plugin, to report missing
+//! binaries before the first download.
+
+pub use plugin_tools::binaries::{binaries, set_binaries, Binaries, MissingDependency};
+pub(crate) use plugin_tools::binaries::{ffmpeg, spawn_error, yt_dlp};
+
+/// Checks that every binary can be run. Returns the first one missing.
+pub fn probe() -> Result<(), MissingDependency> {
+    plugin_tools::binaries::check(yt_dlp(), "yt-dlp", "--version")?;
+    plugin_tools::binaries::check(ffmpeg(), "ffmpeg", "-version")
+}
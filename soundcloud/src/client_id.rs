@@ -0,0 +1,117 @@
+//! The `client_id` the public SoundCloud API requires.
+//!
+//! SoundCloud doesn't hand out API keys anymore, but its web client embeds one
+//! in the scripts it loads. It's scraped from there, like other tools do, and
+//! kept until the API refuses it.
+
+use crate::USER_AGENT;
+use regex::Regex;
+use std::{error::Error, sync::Mutex};
+
+const HOME_URL: &'static str = "https://soundcloud.com";
+
+static CLIENT_ID: Mutex<Option<String>> = Mutex::new(None);
+
+/// Returns the cached `client_id`, scraping a new one if needed. The cache
+/// isn't locked while scraping, searches started meanwhile may scrape too.
+pub(crate) fn get() -> Result<String, Box<dyn Error>> {
+    if let Some(client_id) = CLIENT_ID.lock().unwrap().as_ref() {
+        return Ok(client_id.clone());
+    }
+
+    // `Plugin::query` isn't async and may run inside a runtime
+    let scraped = crate::blocking(|| scrape().map_err(|err| err.to_string()))??;
+    *CLIENT_ID.lock().unwrap() = Some(scraped.clone());
+
+    Ok(scraped)
+}
+
+/// Caches `client_id`, as if it was scraped
+#[cfg(test)]
+pub(crate) fn set(client_id: &str) {
+    *CLIENT_ID.lock().unwrap() = Some(client_id.to_string());
+}
+
+/// Forgets the cached `client_id`, so that the next request scrapes a new one
+pub(crate) fn invalidate() {
+    *CLIENT_ID.lock().unwrap() = None;
+}
+
+/// Runs `request` with the cached `client_id`. If the API refuses it, runs
+/// `request` once more with a new one.
+pub(crate) fn with_client_id<T>(
+    request: impl Fn(&str) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    match request(&get()?) {
+        Err(err) if is_refused(err.as_ref()) => {
+            invalidate();
+            request(&get()?)
+        }
+        result => result,
+    }
+}
+
+/// Whether `err` is the answer of the API to an expired `client_id`
+fn is_refused(err: &(dyn Error + 'static)) -> bool {
+    err.downcast_ref::<reqwest::Error>()
+        .and_then(reqwest::Error::status)
+        == Some(reqwest::StatusCode::UNAUTHORIZED)
+}
+
+fn scrape() -> Result<String, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()?;
+    let home = client.get(HOME_URL).send()?.text()?;
+
+    // The id is defined by one of the last scripts loaded
+    for script in script_urls(&home).iter().rev() {
+        let script = client.get(script).send()?.text()?;
+        if let Some(client_id) = find_client_id(&script) {
+            return Ok(client_id);
+        }
+    }
+
+    Err("No client_id found in the SoundCloud scripts".into())
+}
+
+/// Urls of the scripts loaded by a SoundCloud page
+fn script_urls(page: &str) -> Vec<String> {
+    let regex = Regex::new(r#"<script[^>]+src="(https://[^"]+\.js)""#).unwrap();
+    regex
+        .captures_iter(page)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+fn find_client_id(script: &str) -> Option<String> {
+    let regex = Regex::new(r#"client_id\s*[:=]\s*"([0-9A-Za-z]{32})""#).unwrap();
+    regex
+        .captures(script)
+        .map(|captures| captures[1].to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_client_id, script_urls};
+
+    #[test]
+    fn scraping() {
+        let home = r#"<script crossorigin src="https://a-v2.sndcdn.com/assets/49-4786eb1e.js"></script>
+            <script crossorigin src="https://a-v2.sndcdn.com/assets/2-9a5b1b6c.js"></script>"#;
+        assert_eq!(
+            script_urls(home),
+            vec![
+                "https://a-v2.sndcdn.com/assets/49-4786eb1e.js",
+                "https://a-v2.sndcdn.com/assets/2-9a5b1b6c.js"
+            ]
+        );
+
+        let script = r#"e.exports={api:"https://api-v2.soundcloud.com",client_id:"a281614d7f34dc30b665dfcaa3ed7505",env:"production"}"#;
+        assert_eq!(
+            find_client_id(script).as_deref(),
+            Some("a281614d7f34dc30b665dfcaa3ed7505")
+        );
+        assert_eq!(find_client_id("client_id:null"), None);
+    }
+}
@@ -0,0 +1,30 @@
+//! Limit on the processes the plugin runs at the same time.
+//!
+//! Once [`set_max_processes`] is set, starting a download or a stream over
+//! the limit fails with a [`BusyError`] rather than waiting for a slot, see
+//! `plugin_tools::concurrency`. Processes handed over to the host count until
+//! they exit. The limit only applies to this plugin, other plugins have their
+//! own.
+
+pub use plugin_tools::concurrency::BusyError;
+use plugin_tools::concurrency::{Limiter, Permit};
+use std::sync::RwLock;
+
+static MAX_PROCESSES: RwLock<Option<usize>> = RwLock::new(None);
+static LIMITER: Limiter = Limiter::new();
+
+/// Limits the processes running at the same time to `max`, at least one, or
+/// lifts the limit with `None`, the default
+pub fn set_max_processes(max: Option<usize>) {
+    *MAX_PROCESSES.write().unwrap() = max.map(|max| max.max(1));
+}
+
+/// Returns the limit on the processes running at the same time
+pub fn max_processes() -> Option<usize> {
+    *MAX_PROCESSES.read().unwrap()
+}
+
+/// Takes a slot for a process, unless the limit is reached
+pub(crate) fn acquire() -> Result<Permit<'static>, BusyError> {
+    LIMITER.acquire(max_processes())
+}
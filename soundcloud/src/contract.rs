@@ -0,0 +1,19 @@
+//! Contract tests towards phosphorus_core, see `plugin_contract`.
+
+use crate::{client_id, SoundCloud};
+use std::path::Path;
+
+#[test]
+fn plugin_contract() {
+    plugin_contract::check_plugin(&SoundCloud {});
+
+    // Not scraped during the tests
+    client_id::set("a281614d7f34dc30b665dfcaa3ed7505");
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    plugin_contract::check_search(
+        &SoundCloud {},
+        "never gonna give you up",
+        &plugin_contract::fixtures(&tests.join("corpus")),
+        &tests.join("snapshots"),
+    );
+}
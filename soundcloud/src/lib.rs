@@ -0,0 +1,316 @@
+use async_trait::async_trait;
+use phosphorus_core::plugin_manager::{
+    downloader::*,
+    error::ParseError,
+    plugin::Plugin,
+    query::*,
+    streamer::{StreamBuilder, Streamer},
+};
+use serde_json::Value;
+use std::{
+    error::Error,
+    process::{Child, Stdio},
+    thread,
+    time::Duration,
+};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+pub mod binaries;
+mod client_id;
+pub mod concurrency;
+#[cfg(test)]
+mod contract;
+#[cfg(test)]
+mod live;
+mod media;
+pub mod stream;
+
+pub use plugin_tools::download;
+
+const API_URL: &'static str = "https://api-v2.soundcloud.com";
+const BASE_URL: &'static str = "https://api-v2.soundcloud.com/search/tracks";
+const USER_AGENT: &'static str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/104.0.5112.102 Safari/537.36";
+/// Results requested per search
+const PAGE_SIZE: &'static str = "20";
+
+/// Artwork urls end with the size they're served at, `-large` being 100x100
+const LARGE_ARTWORK: &'static str = "-large.";
+const TRACK_ARTWORK: &'static str = "-t300x300.";
+
+/// Plugin that allows to query SoundCloud and
+/// retrieve download-usefull information
+pub struct SoundCloud {}
+
+#[async_trait]
+impl Plugin for SoundCloud {
+    fn method(&self) -> reqwest::Method {
+        reqwest::Method::GET
+    }
+
+    fn base_url(&self) -> &'static str {
+        BASE_URL
+    }
+
+    fn query(
+        &self,
+        info: &QueryInfo,
+        mut req_builder: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Request, Box<dyn Error>> {
+        let client_id = client_id::get()?;
+        req_builder = req_builder.query(&[
+            ("q", info.raw()),
+            ("client_id", &client_id),
+            ("limit", PAGE_SIZE),
+        ]);
+        req_builder = req_builder.header("user-agent", USER_AGENT);
+        Ok(req_builder.build()?)
+    }
+
+    async fn parse(
+        &self,
+        _info: &QueryInfo,
+        resp: reqwest::Response,
+    ) -> Result<QueryResult, Box<dyn Error>> {
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            // The scraped client_id expired, the next search scrapes a new one
+            client_id::invalidate();
+            return Err(Box::new(ParseError::InvalidResponseText));
+        }
+        let text = resp.text().await?;
+        let json: Value = match serde_json::from_str(&text) {
+            Ok(json) => json,
+            Err(cause) => return Err(Box::new(ParseError::JsonUnparsable(cause.to_string()))),
+        };
+
+        Ok(QueryResult::new(parse_tracks(&json)?))
+    }
+
+    fn download(&self) -> Downloader {
+        DownloadBuilder::new(download_fn)
+    }
+
+    fn stream(&self) -> Streamer {
+        StreamBuilder::new(stream_fn)
+    }
+}
+
+/// Creates a sub-process that downloads the media associated to `url`. That media is than
+/// saved as `file_name.[ext]`, in the format set with `download::set_options`. The handler
+/// to the sub-process is returned. It's refused over the `concurrency` limit.
+pub fn download_fn(url: &str, file_name: &str) -> Result<Child, Box<dyn Error>> {
+    let options = download::options();
+    // yt-dlp handles both progressive and HLS transcodings
+    let mut download_command = binaries::yt_dlp();
+    download_command.args(download::extractor_args(&options));
+    download_command.args(download::output_args(&options, file_name));
+    download_command.arg(url);
+
+    let permit = concurrency::acquire()?;
+    let handler = download_command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| binaries::spawn_error("yt-dlp", err))?;
+    permit.hand_over(handler.id());
+
+    Ok(handler)
+}
+
+/// Creates a sub-process that streams the media associated to `url` into `file_name`,
+/// encoded as set with `stream::set_options`. The handler to the sub-process is returned.
+pub fn stream_fn(url: &str, file_name: &str) -> Result<std::process::Child, Box<dyn Error>> {
+    let url = media::resolve_stream_url(url)?;
+
+    let permit = concurrency::acquire()?;
+    let handler = stream::command(&stream::options(), &url, file_name)?
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| binaries::spawn_error("ffmpeg", err))?;
+    permit.hand_over(handler.id());
+
+    Ok(handler)
+}
+
+/// Runs `task`, that uses the blocking client, from synchronous code that may
+/// run inside an async runtime, where that client can't be used directly. It
+/// runs on a thread of its own, and the worker of a multi-threaded runtime is
+/// handed over to its other tasks meanwhile.
+fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, Box<dyn Error>> {
+    let handle = match Handle::try_current() {
+        Ok(handle) => handle,
+        Err(_) => return Ok(task()),
+    };
+    let spawned = || {
+        thread::spawn(task)
+            .join()
+            .map_err(|_| Box::<dyn Error>::from("A blocking request panicked"))
+    };
+    match handle.runtime_flavor() {
+        RuntimeFlavor::MultiThread => tokio::task::block_in_place(spawned),
+        _ => spawned(),
+    }
+}
+
+/// Parses the tracks listed in a search response. Malformed tracks are
+/// skipped and logged, the others are still listed.
+fn parse_tracks(json: &Value) -> Result<Vec<QueryResultData>, Box<dyn Error>> {
+    let collection = match json.read("collection")?.as_array() {
+        Some(collection) => collection,
+        None => return Err(Box::new(ParseError::InvalidResponseText)),
+    };
+    let mut items = vec![];
+    for track in collection {
+        match QueryResultData::parse(track) {
+            Ok(item) => items.push(item),
+            Err(err) => log::warn!(
+                "Skipped malformed track {}: {}",
+                track.get("id").unwrap_or(&Value::Null),
+                err
+            ),
+        }
+    }
+    if items.len() < collection.len() {
+        log::warn!(
+            "{} malformed tracks skipped",
+            collection.len() - items.len()
+        );
+    }
+
+    Ok(items)
+}
+
+impl Deserializable<Value, QueryResultData, SoundCloud> for QueryResultData {
+    fn parse(source: &Value) -> Result<Self, Box<dyn Error>> {
+        let track_id = match source.read("id")?.as_u64() {
+            Some(id) => id.to_string(),
+            None => return Err(Box::new(ParseError::InvalidResponseText)),
+        };
+        let track_name = source.read_str("title")?;
+        let track_url = reqwest::Url::parse(source.read_str("permalink_url")?)?;
+
+        let user = source.read("user")?;
+        let artist_name = user.read_str("username")?;
+        let artist_thumbnail = user.read_str("avatar_url")?;
+        // Tracks without artwork, null or missing, are shown with the avatar
+        // of their artist
+        let track_thumbnail = source
+            .get("artwork_url")
+            .and_then(Value::as_str)
+            .unwrap_or(artist_thumbnail)
+            .replace(LARGE_ARTWORK, TRACK_ARTWORK);
+
+        let duration = match source.read("duration")?.as_u64() {
+            Some(millis) => Duration::from_millis(millis),
+            None => return Err(Box::new(ParseError::InvalidResponseText)),
+        };
+
+        Ok(QueryResultData::new(
+            &track_id,
+            track_name,
+            track_url,
+            reqwest::Url::parse(&track_thumbnail)?,
+            artist_name,
+            reqwest::Url::parse(artist_thumbnail)?,
+            duration,
+        ))
+    }
+}
+
+trait SCItemParser {
+    /// Utility to retrieve a `Value` from a map representing a JSON
+    fn read(&self, field_name: &str) -> Result<&Value, Box<dyn Error>>;
+
+    /// Utility to retrieve a string from a map representing a JSON
+    fn read_str(&self, field_name: &str) -> Result<&str, Box<dyn Error>>;
+}
+
+impl SCItemParser for Value {
+    fn read(&self, field_name: &str) -> Result<&Value, Box<dyn Error>> {
+        match self.get(field_name) {
+            Some(value) => Ok(value),
+            None => Err(Box::new(ParseError::JsonUnparsable(format!(
+                "Missing field `{}`",
+                field_name
+            )))),
+        }
+    }
+
+    fn read_str(&self, field_name: &str) -> Result<&str, Box<dyn Error>> {
+        match self.read(field_name)?.as_str() {
+            Some(text) => Ok(text),
+            None => Err(Box::new(ParseError::JsonUnparsable(format!(
+                "Field `{}` isn't a string",
+                field_name
+            )))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{blocking, parse_tracks};
+    use phosphorus_core::plugin_manager::query::{Deserializable, QueryResultData};
+
+    #[test]
+    fn track_parsing() {
+        let track = serde_json::json!({
+            "id": 1242868615,
+            "title": "Flume - Say Nothing feat. MAY-A",
+            "permalink_url": "https://soundcloud.com/flume/say-nothing",
+            "artwork_url": null,
+            "duration": 213746,
+            "user": {
+                "username": "Flume",
+                "avatar_url": "https://i1.sndcdn.com/avatars-000645528297-wmrmq5-large.jpg"
+            }
+        });
+        assert!(QueryResultData::parse(&track).is_ok());
+
+        let mut without_artwork = track.clone();
+        without_artwork
+            .as_object_mut()
+            .unwrap()
+            .remove("artwork_url");
+        assert!(QueryResultData::parse(&without_artwork).is_ok());
+
+        let mut private = track.clone();
+        private["user"] = serde_json::Value::Null;
+        assert!(QueryResultData::parse(&private).is_err());
+
+        let search = serde_json::json!({ "collection": [track, private] });
+        assert_eq!(
+            parse_tracks(&search).unwrap().len(),
+            1,
+            "The malformed track is skipped"
+        );
+    }
+
+    #[test]
+    fn blocking_inside_runtimes() {
+        assert_eq!(blocking(|| 1).unwrap(), 1);
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waiting = runtime.block_on(async {
+            let waiting = tokio::spawn(async move {
+                blocking(move || receiver.recv_timeout(std::time::Duration::from_secs(5)))
+                    .map_err(|err| err.to_string())
+            });
+            // Runs on the only worker, while the other task blocks
+            tokio::spawn(async move { sender.send(2).unwrap() });
+            waiting.await.unwrap()
+        });
+        assert_eq!(waiting.unwrap(), Ok(2));
+
+        let current = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        assert_eq!(current.block_on(async { blocking(|| 3).unwrap() }), 3);
+    }
+}
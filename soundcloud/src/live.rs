@@ -0,0 +1,96 @@
+//! Checks against live SoundCloud, ignored by default.
+//!
+//! They run a real search, a small download and a short stream, with the
+//! binaries installed on the machine, and print a scorecard telling which
+//! step is broken upstream. Run them with
+//! `cargo test live -- --ignored --nocapture`.
+
+use crate::{client_id, download_fn, parse_tracks, stream_fn, BASE_URL, USER_AGENT};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A short Creative Commons track
+const TRACK_URL: &'static str = "https://soundcloud.com/forss/flickermood";
+const QUERY: &'static str = "forss flickermood";
+
+fn workspace() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("phosphorus-live-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn search() -> Result<(), Box<dyn Error>> {
+    let client_id = client_id::get()?;
+    let text = reqwest::blocking::Client::new()
+        .get(BASE_URL)
+        .query(&[("q", QUERY), ("client_id", &client_id)])
+        .header("user-agent", USER_AGENT)
+        .send()?
+        .error_for_status()?
+        .text()?;
+    if parse_tracks(&serde_json::from_str(&text)?)?.is_empty() {
+        return Err("No result".into());
+    }
+
+    Ok(())
+}
+
+fn download(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let file_name = dir.join("download");
+    let status = download_fn(TRACK_URL, &file_name.to_string_lossy())?.wait()?;
+    if !status.success() {
+        return Err(format!("yt-dlp exited with {}", status).into());
+    }
+    if !dir.join("download.mp3").exists() {
+        return Err("No file downloaded".into());
+    }
+
+    Ok(())
+}
+
+fn stream(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let file_name = dir.join("stream.mp3");
+    let mut child = stream_fn(TRACK_URL, &file_name.to_string_lossy())?;
+    // A few seconds are enough to tell the stream works
+    std::thread::sleep(std::time::Duration::from_secs(5));
+    if let Some(status) = child.try_wait()? {
+        if !status.success() {
+            return Err(format!("ffmpeg exited with {}", status).into());
+        }
+    } else {
+        child.kill()?;
+        child.wait()?;
+    }
+    if fs::metadata(&file_name)?.len() == 0 {
+        return Err("Empty stream".into());
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "needs network access, yt-dlp and ffmpeg"]
+fn scorecard() {
+    let dir = workspace();
+    let checks = [
+        ("search", search()),
+        ("download", download(&dir)),
+        ("stream", stream(&dir)),
+    ];
+    let _ = fs::remove_dir_all(&dir);
+
+    println!("\nsoundcloud scorecard");
+    for (name, result) in &checks {
+        match result {
+            Ok(()) => println!("  {:<10} ok", name),
+            Err(err) => println!("  {:<10} BROKEN: {}", name, err),
+        }
+    }
+    assert!(
+        checks.iter().all(|(_, result)| result.is_ok()),
+        "Some checks are broken"
+    );
+}
@@ -0,0 +1,115 @@
+//! Resolution of the audio streams of a track.
+//!
+//! Every track lists its transcodings, each one served either progressively,
+//! as a single file, or through HLS. The url of a transcoding only leads to
+//! the url of the actual stream, which is short-lived. A `client_id` refused
+//! on the way is scraped again once.
+
+use crate::{client_id, SCItemParser, API_URL, USER_AGENT};
+use phosphorus_core::plugin_manager::error::PluginError;
+use serde_json::Value;
+use std::error::Error;
+
+/// How a transcoding is served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    Progressive,
+    Hls,
+}
+
+/// Returns the url of the audio stream of the track at `url`. Progressive
+/// streams are preferred, since they can be seeked without a playlist.
+pub(crate) fn resolve_stream_url(url: &str) -> Result<String, Box<dyn Error>> {
+    let url = url.to_string();
+    // Called from the synchronous `stream_fn`, possibly inside a runtime
+    crate::blocking(move || {
+        client_id::with_client_id(|client_id| resolve(&url, client_id))
+            .map_err(|err| err.to_string())
+    })?
+    .map_err(|err| PluginError::StreamError(err).into())
+}
+
+fn resolve(url: &str, client_id: &str) -> Result<String, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()?;
+
+    let track = client
+        .get(format!("{}/resolve", API_URL))
+        .query(&[("url", url), ("client_id", client_id)])
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let track: Value = serde_json::from_str(&track)?;
+    let transcoding = pick_transcoding(&track)?;
+
+    let stream = client
+        .get(transcoding)
+        .query(&[("client_id", client_id)])
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let stream: Value = serde_json::from_str(&stream)?;
+
+    Ok(stream.read_str("url")?.to_string())
+}
+
+/// Returns the url of the best transcoding of `track`
+fn pick_transcoding(track: &Value) -> Result<&str, Box<dyn Error>> {
+    let transcodings = track
+        .read("media")?
+        .read("transcodings")?
+        .as_array()
+        .ok_or("Transcodings aren't a list")?;
+
+    let mut best = None;
+    for transcoding in transcodings {
+        let protocol = match transcoding.read("format")?.read_str("protocol")? {
+            "progressive" => Protocol::Progressive,
+            "hls" => Protocol::Hls,
+            _ => continue,
+        };
+        match (best, protocol) {
+            (None, _) | (Some((Protocol::Hls, _)), Protocol::Progressive) => {
+                best = Some((protocol, transcoding.read_str("url")?))
+            }
+            _ => {}
+        }
+    }
+
+    match best {
+        Some((_, url)) => Ok(url),
+        None => Err("No playable transcoding".into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::pick_transcoding;
+
+    #[test]
+    fn transcodings() {
+        let track = serde_json::json!({ "media": { "transcodings": [
+            {
+                "url": "https://api-v2.soundcloud.com/media/soundcloud:tracks:1/0/stream/hls",
+                "format": { "protocol": "hls", "mime_type": "audio/mpeg" }
+            },
+            {
+                "url": "https://api-v2.soundcloud.com/media/soundcloud:tracks:1/0/stream/progressive",
+                "format": { "protocol": "progressive", "mime_type": "audio/mpeg" }
+            }
+        ]}});
+        assert!(pick_transcoding(&track).unwrap().ends_with("/progressive"));
+
+        let track = serde_json::json!({ "media": { "transcodings": [
+            {
+                "url": "https://api-v2.soundcloud.com/media/soundcloud:tracks:1/0/stream/hls",
+                "format": { "protocol": "hls", "mime_type": "audio/ogg; codecs=\"opus\"" }
+            }
+        ]}});
+        assert!(pick_transcoding(&track).unwrap().ends_with("/hls"));
+
+        let track = serde_json::json!({ "media": { "transcodings": [] } });
+        assert!(pick_transcoding(&track).is_err());
+    }
+}
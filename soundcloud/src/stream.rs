@@ -0,0 +1,107 @@
+//! Options applied to the streams started by `stream_fn`.
+//!
+//! Streams are re-encoded by `ffmpeg` into the file passed to `stream_fn`.
+//! The host picks the codec and the bitrate with [`set_options`].
+
+use plugin_tools::{
+    codec::{self, UnknownContainer},
+    download::AudioFormat,
+};
+use std::{process::Command, sync::RwLock};
+
+/// Options of the streams
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StreamOptions {
+    /// Codec the stream is encoded with. `Best` encodes to the codec the
+    /// extension of the file calls for, and keeps the source codec when the
+    /// container takes any codec (`.mka`). Extensions without a known codec
+    /// are refused.
+    pub format: AudioFormat,
+    /// Target bitrate, in kbit/s. Ignored by lossless codecs.
+    pub bitrate: Option<u32>,
+}
+
+impl StreamOptions {
+    const fn new() -> Self {
+        StreamOptions {
+            format: AudioFormat::Mp3,
+            bitrate: None,
+        }
+    }
+}
+
+static OPTIONS: RwLock<StreamOptions> = RwLock::new(StreamOptions::new());
+
+/// Applies `options` to the streams started from now on
+pub fn set_options(options: StreamOptions) {
+    *OPTIONS.write().unwrap() = options;
+}
+
+/// Returns the options applied to new streams
+pub fn options() -> StreamOptions {
+    OPTIONS.read().unwrap().clone()
+}
+
+/// Builds the `ffmpeg` command that encodes the audio at `url` into
+/// `file_name` with `options`. Fails if the codec of `Best` can't be told
+/// from the extension of `file_name`.
+pub(crate) fn command(
+    options: &StreamOptions,
+    url: &str,
+    file_name: &str,
+) -> Result<Command, UnknownContainer> {
+    // ffmpeg reads HLS playlists as well as single files
+    let mut command = crate::binaries::ffmpeg();
+    command.args(["-i", url]);
+    let codec = codec::codec(options.format, file_name, true)?;
+    command.args(["-c:a", codec]);
+    if let Some(bitrate) = options.bitrate {
+        if codec::has_bitrate(codec) {
+            command.args(["-b:a", &format!("{}k", bitrate)]);
+        }
+    }
+    command.arg(format!("file:{}", file_name));
+    command.arg("-y"); // If `file_name` exists, it's overwritten
+
+    Ok(command)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{command, StreamOptions};
+    use plugin_tools::download::AudioFormat;
+
+    #[test]
+    fn stream_arguments() {
+        let args = |options: &StreamOptions, file_name: &str| -> Vec<String> {
+            command(options, "https://cf-media.sndcdn.com/a.mp3", file_name)
+                .unwrap()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(
+            args(&StreamOptions::default(), "out.mp3"),
+            vec![
+                "-i",
+                "https://cf-media.sndcdn.com/a.mp3",
+                "-c:a",
+                "libmp3lame",
+                "file:out.mp3",
+                "-y"
+            ]
+        );
+
+        let opus = StreamOptions {
+            format: AudioFormat::Opus,
+            bitrate: Some(96),
+        };
+        assert_eq!(args(&opus, "out.opus")[3..6], ["libopus", "-b:a", "96k"]);
+        let best = StreamOptions {
+            format: AudioFormat::Best,
+            bitrate: Some(96),
+        };
+        assert_eq!(args(&best, "out.mka")[3..5], ["copy", "file:out.mka"]);
+        assert!(command(&best, "https://cf-media.sndcdn.com/a.mp3", "out").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Paths of the binaries the plugins run.
+//!
+//! By default `yt-dlp`, `youtube-dl` and `ffmpeg` are looked up in `PATH`.
+//! [`set_binaries`] points every plugin to other executables, like a bundled
+//! copy or, in tests, a fake that prints scripted output. Each plugin probes
+//! the binaries it needs with [`check`].
+
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::RwLock,
+};
+
+/// A binary a plugin needs can't be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDependency(pub String);
+
+impl fmt::Display for MissingDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` not found, install it or set its path with `binaries::set_binaries`",
+            self.0
+        )
+    }
+}
+
+impl Error for MissingDependency {}
+
+/// Executables run in place of the ones in `PATH`. `None` keeps the default.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Binaries {
+    /// Downloads the media
+    pub yt_dlp: Option<PathBuf>,
+    /// Resolves the url of audio streams
+    pub youtube_dl: Option<PathBuf>,
+    /// Transcodes streams and analyzes audio
+    pub ffmpeg: Option<PathBuf>,
+}
+
+impl Binaries {
+    const fn new() -> Self {
+        Binaries {
+            yt_dlp: None,
+            youtube_dl: None,
+            ffmpeg: None,
+        }
+    }
+}
+
+static BINARIES: RwLock<Binaries> = RwLock::new(Binaries::new());
+
+/// Runs the executables in `binaries` from now on
+pub fn set_binaries(binaries: Binaries) {
+    *BINARIES.write().unwrap() = binaries;
+}
+
+/// Returns the executables run in place of the ones in `PATH`
+pub fn binaries() -> Binaries {
+    BINARIES.read().unwrap().clone()
+}
+
+pub fn yt_dlp() -> Command {
+    command(binaries().yt_dlp, "yt-dlp")
+}
+
+pub fn youtube_dl() -> Command {
+    command(binaries().youtube_dl, "youtube-dl")
+}
+
+pub fn ffmpeg() -> Command {
+    command(binaries().ffmpeg, "ffmpeg")
+}
+
+fn command(path: Option<PathBuf>, default: &str) -> Command {
+    match path {
+        Some(path) => Command::new(path),
+        None => Command::new(default),
+    }
+}
+
+/// Checks that `binary` can be run by `command`, by asking its version with
+/// `version_flag`
+pub fn check(
+    mut command: Command,
+    binary: &str,
+    version_flag: &str,
+) -> Result<(), MissingDependency> {
+    let status = command
+        .arg(version_flag)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Err(err)
+            if err.kind() == ErrorKind::NotFound || err.kind() == ErrorKind::PermissionDenied =>
+        {
+            Err(MissingDependency(binary.into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Turns the error of spawning `binary` into the one returned to the host,
+/// a `MissingDependency` if the binary doesn't exist
+pub fn spawn_error(binary: &str, err: io::Error) -> Box<dyn Error> {
+    if err.kind() == ErrorKind::NotFound {
+        return Box::new(MissingDependency(binary.into()));
+    }
+
+    Box::new(err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, spawn_error, MissingDependency};
+    use std::process::Command;
+
+    #[test]
+    fn missing_binaries() {
+        assert_eq!(
+            check(
+                Command::new("phosphorus-missing-binary"),
+                "ffmpeg",
+                "-version"
+            ),
+            Err(MissingDependency("ffmpeg".into()))
+        );
+
+        let err = Command::new("phosphorus-missing-binary")
+            .spawn()
+            .map_err(|err| spawn_error("yt-dlp", err))
+            .unwrap_err();
+        assert!(err.downcast_ref::<MissingDependency>().is_some());
+    }
+}
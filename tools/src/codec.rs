@@ -0,0 +1,60 @@
+//! Audio codecs `ffmpeg` encodes streams with.
+
+use crate::download::AudioFormat;
+use std::{error::Error, fmt, path::Path};
+
+/// Error returned when `Best` can't tell the codec the extension of a file
+/// calls for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownContainer(pub String);
+
+impl fmt::Display for UnknownContainer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No codec known for `{}`, pick a format or another extension",
+            self.0
+        )
+    }
+}
+
+impl Error for UnknownContainer {}
+
+/// `ffmpeg` audio codec encoding `format` into `file_name`. `Best` encodes
+/// to the codec the extension of the file calls for, and keeps the source
+/// codec when `copy` allows it and the container takes any codec (`.mka`).
+/// Extensions without a known codec are refused.
+pub fn codec(
+    format: AudioFormat,
+    file_name: &str,
+    copy: bool,
+) -> Result<&'static str, UnknownContainer> {
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let codec = match format {
+        AudioFormat::Mp3 => "libmp3lame",
+        AudioFormat::Opus => "libopus",
+        AudioFormat::M4a => "aac",
+        AudioFormat::Flac => "flac",
+        // The source codec, opus or aac, may not fit the container
+        AudioFormat::Best => match extension.as_deref() {
+            Some("mp3") => "libmp3lame",
+            Some("m4a" | "aac" | "mp4") => "aac",
+            Some("flac") => "flac",
+            Some("wav") => "pcm_s16le",
+            Some("mka" | "mkv") if copy => "copy",
+            // Ogg takes vorbis too, opus is kept for its quality at low bitrates
+            Some("opus" | "ogg" | "oga" | "webm" | "mka" | "mkv") => "libopus",
+            _ => return Err(UnknownContainer(file_name.into())),
+        },
+    };
+
+    Ok(codec)
+}
+
+/// Whether `codec` takes a target bitrate
+pub fn has_bitrate(codec: &str) -> bool {
+    !matches!(codec, "flac" | "pcm_s16le" | "copy")
+}
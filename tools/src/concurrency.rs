@@ -0,0 +1,171 @@
+//! Limit on the processes a plugin runs at the same time.
+//!
+//! Each plugin keeps a [`Limiter`] of its own, so the limit set for one
+//! doesn't hold back the others. The limiter isn't a semaphore callers wait
+//! on: taking a slot over the limit fails at once with a [`BusyError`], since
+//! the caller may be running an async executor, and the host retries later.
+//! Processes handed over to the host count until they exit. On platforms
+//! other than Unix, their exit can't be observed, so they aren't counted.
+
+use std::{error::Error, fmt, sync::Mutex};
+
+/// Error returned when the limit on the processes is reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusyError {
+    pub max_processes: usize,
+}
+
+impl fmt::Display for BusyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} processes are running already, retry once one of them exits",
+            self.max_processes
+        )
+    }
+}
+
+impl Error for BusyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// Taken by a permit, until dropped
+    Held(u64),
+    /// Taken by a process the host owns, until it exits
+    Process(u32),
+}
+
+/// Slots of the processes of a plugin
+pub struct Limiter {
+    slots: Mutex<(u64, Vec<Slot>)>,
+}
+
+impl Limiter {
+    pub const fn new() -> Self {
+        Limiter {
+            slots: Mutex::new((0, Vec::new())),
+        }
+    }
+
+    /// Takes a slot for a process, unless `max` are taken already
+    pub fn acquire(&self, max: Option<usize>) -> Result<Permit<'_>, BusyError> {
+        let mut slots = self.slots.lock().unwrap();
+        let (next_id, taken) = &mut *slots;
+        taken.retain(|slot| match slot {
+            Slot::Held(_) => true,
+            Slot::Process(pid) => is_running(*pid),
+        });
+        if let Some(max) = max {
+            if taken.len() >= max {
+                return Err(BusyError { max_processes: max });
+            }
+        }
+
+        let id = *next_id;
+        *next_id += 1;
+        taken.push(Slot::Held(id));
+        Ok(Permit { limiter: self, id })
+    }
+
+    fn replace(&self, id: u64, slot: Option<Slot>) {
+        let mut slots = self.slots.lock().unwrap();
+        let taken = &mut slots.1;
+        if let Some(index) = taken.iter().position(|held| *held == Slot::Held(id)) {
+            match slot {
+                Some(slot) => taken[index] = slot,
+                None => {
+                    taken.remove(index);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Limiter::new()
+    }
+}
+
+/// Right to run a process, given back when dropped
+pub struct Permit<'l> {
+    limiter: &'l Limiter,
+    id: u64,
+}
+
+impl Permit<'_> {
+    /// Keeps the slot taken until the process `pid`, owned by the host, exits
+    pub fn hand_over(self, pid: u32) {
+        self.limiter.replace(self.id, Some(Slot::Process(pid)));
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.replace(self.id, None);
+    }
+}
+
+/// Whether the child process `pid` hasn't exited yet. The host owns its
+/// `Child`, so it's checked like `Child::try_wait` does, without reaping it:
+/// an exited process waiting to be reaped isn't running, and a pid that isn't
+/// a child of the plugin anymore, reused or not, was reaped already.
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let status = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            pid as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    // Without a state change to report, the pid of `info` is left to 0
+    status == 0 && unsafe { info.si_pid() } == 0
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BusyError, Limiter};
+
+    #[test]
+    fn refuses_over_limit() {
+        static LIMITER: Limiter = Limiter::new();
+        let first = LIMITER.acquire(Some(1)).unwrap();
+        assert_eq!(
+            LIMITER.acquire(Some(1)).err(),
+            Some(BusyError { max_processes: 1 }),
+            "The second permit doesn't wait for the first one"
+        );
+
+        drop(first);
+        let second = LIMITER.acquire(Some(1));
+        assert!(second.is_ok());
+        drop(second);
+
+        let unlimited: Vec<_> = (0..8).map(|_| LIMITER.acquire(None).unwrap()).collect();
+        assert_eq!(unlimited.len(), 8);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exited_processes_free_their_slot() {
+        static LIMITER: Limiter = Limiter::new();
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "exit 0"])
+            .spawn()
+            .unwrap();
+        LIMITER.acquire(Some(1)).unwrap().hand_over(child.id());
+        // Exited, but not waited for by its owner yet
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(LIMITER.acquire(Some(1)).is_ok());
+        assert!(child.try_wait().unwrap().is_some(), "Left to its owner");
+    }
+}
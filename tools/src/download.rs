@@ -0,0 +1,158 @@
+//! Options applied to the downloads of every plugin.
+//!
+//! Options shared by every download are set with [`set_options`]. Each
+//! plugin translates them to the arguments of its extractor with
+//! [`extractor_args`] and [`output_args`].
+
+use std::{path::PathBuf, sync::RwLock};
+
+/// Placeholder of the output template replaced by the name of the file
+const FILE_NAME_FIELD: &'static str = "{file_name}";
+const DEFAULT_TEMPLATE: &'static str = "{file_name}.%(ext)s";
+
+/// Format audio is saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    Opus,
+    M4a,
+    Flac,
+    /// Whatever the source provides, without re-encoding
+    Best,
+}
+
+impl AudioFormat {
+    /// Name of the format for `yt-dlp`
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Opus => "opus",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Flac => "flac",
+            AudioFormat::Best => "best",
+        }
+    }
+}
+
+/// Options of the downloads
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DownloadOptions {
+    /// Downloads album-length videos as one continuous file with embedded
+    /// chapter markers, preserving gapless transitions between tracks. The
+    /// file is always saved as m4a.
+    pub album_mode: bool,
+    pub format: AudioFormat,
+    /// Target bitrate, in kbit/s. Ignored by lossless formats.
+    pub bitrate: Option<u32>,
+    /// Directory files are saved in, instead of the working directory
+    pub output_dir: Option<PathBuf>,
+    /// `yt-dlp` output template, where `{file_name}` is replaced by the name
+    /// passed to the `download_fn` of the plugin. Defaults to `{file_name}.%(ext)s`.
+    pub output_template: Option<String>,
+}
+
+impl DownloadOptions {
+    const fn new() -> Self {
+        DownloadOptions {
+            album_mode: false,
+            format: AudioFormat::Mp3,
+            bitrate: None,
+            output_dir: None,
+            output_template: None,
+        }
+    }
+}
+
+static OPTIONS: RwLock<DownloadOptions> = RwLock::new(DownloadOptions::new());
+
+/// Applies `options` to the downloads started from now on
+pub fn set_options(options: DownloadOptions) {
+    *OPTIONS.write().unwrap() = options;
+}
+
+/// Returns the options applied to new downloads
+pub fn options() -> DownloadOptions {
+    OPTIONS.read().unwrap().clone()
+}
+
+/// Translates `options` to `yt-dlp` arguments
+pub fn extractor_args(options: &DownloadOptions) -> Vec<String> {
+    let format = if options.album_mode {
+        // AAC in an MP4 container is gapless and supports chapters
+        AudioFormat::M4a
+    } else {
+        options.format
+    };
+
+    let mut args = vec![
+        "--extract-audio".to_string(),
+        "--audio-format".into(),
+        format.name().into(),
+    ];
+    if let Some(bitrate) = options.bitrate {
+        args.extend(["--audio-quality".into(), format!("{}K", bitrate)]);
+    }
+    if options.album_mode {
+        args.extend(["--embed-chapters".into(), "--embed-metadata".into()]);
+    }
+
+    args
+}
+
+/// Translates the output options to the `yt-dlp` arguments saving the media
+/// as `file_name`
+pub fn output_args(options: &DownloadOptions, file_name: &str) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(dir) = &options.output_dir {
+        args.extend(["--paths".into(), dir.to_string_lossy().into_owned()]);
+    }
+    let template = options
+        .output_template
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE);
+    args.extend(["-o".into(), template.replace(FILE_NAME_FIELD, file_name)]);
+
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use super::{extractor_args, output_args, AudioFormat, DownloadOptions};
+
+    #[test]
+    fn format_and_output() {
+        let options = DownloadOptions {
+            format: AudioFormat::Opus,
+            bitrate: Some(128),
+            output_dir: Some("/music".into()),
+            output_template: Some("%(uploader)s - {file_name}.%(ext)s".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extractor_args(&options),
+            vec![
+                "--extract-audio",
+                "--audio-format",
+                "opus",
+                "--audio-quality",
+                "128K"
+            ]
+        );
+        assert_eq!(
+            output_args(&options, "track"),
+            vec!["--paths", "/music", "-o", "%(uploader)s - track.%(ext)s"]
+        );
+
+        let album = DownloadOptions {
+            album_mode: true,
+            format: AudioFormat::Flac,
+            ..Default::default()
+        };
+        assert_eq!(extractor_args(&album)[2], "m4a");
+        assert_eq!(
+            output_args(&DownloadOptions::default(), "track"),
+            vec!["-o", "track.%(ext)s"]
+        );
+    }
+}
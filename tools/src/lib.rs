@@ -0,0 +1,11 @@
+//! Settings and helpers shared by the plugins running `yt-dlp` and `ffmpeg`.
+//!
+//! The binaries run and the options of downloads are set once for all the
+//! plugins using them, so a host configures them the same way whatever the
+//! source. Each plugin re-exports these modules, and limits its processes
+//! with a `concurrency::Limiter` of its own.
+
+pub mod binaries;
+pub mod codec;
+pub mod concurrency;
+pub mod download;
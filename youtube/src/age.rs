@@ -0,0 +1,114 @@
+//! How age-restricted videos are handled.
+//!
+//! YouTube only serves age-restricted videos to signed-in adults. Searches
+//! don't tell which videos are restricted, so the policy is enforced by the
+//! extractor, when a video is downloaded or streamed. The extractor exits
+//! with success when it skips a restricted video, so its output is checked
+//! with [`refusal`].
+
+use std::{path::PathBuf, sync::RwLock};
+
+/// Age an extractor skips videos for, restricted videos being for adults
+const MINOR_AGE: &'static str = "17";
+
+/// Handling of age-restricted videos
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgePolicy {
+    /// Age-restricted videos are refused
+    Block,
+    /// Age-restricted videos are fetched with the cookies of a signed-in
+    /// account, exported in the Netscape format to the file
+    WithCookies(PathBuf),
+    /// The extractors try their own workarounds, which may stop working at
+    /// any time. The default.
+    Anonymous,
+}
+
+static POLICY: RwLock<AgePolicy> = RwLock::new(AgePolicy::Anonymous);
+
+/// Applies `policy` to the downloads and streams started from now on
+pub fn set_policy(policy: AgePolicy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+/// Returns the handling of age-restricted videos
+pub fn policy() -> AgePolicy {
+    POLICY.read().unwrap().clone()
+}
+
+/// Translates the policy to extractor arguments, understood by `yt-dlp` and
+/// `youtube-dl` alike
+pub(crate) fn extractor_args() -> Vec<String> {
+    args_for(&POLICY.read().unwrap())
+}
+
+fn args_for(policy: &AgePolicy) -> Vec<String> {
+    match policy {
+        AgePolicy::Block => vec!["--age-limit".into(), MINOR_AGE.into()],
+        AgePolicy::WithCookies(cookies) => {
+            vec!["--cookies".into(), cookies.to_string_lossy().into()]
+        }
+        AgePolicy::Anonymous => vec![],
+    }
+}
+
+/// Reason the extractor gave for skipping an age-restricted video, if
+/// `line` is one
+pub(crate) fn refusal(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.contains("because it is age restricted") {
+        Some(line.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{args_for, AgePolicy};
+
+    #[test]
+    fn extractor_arguments() {
+        assert_eq!(args_for(&AgePolicy::Block), vec!["--age-limit", "17"]);
+        assert_eq!(
+            args_for(&AgePolicy::WithCookies("/home/me/cookies.txt".into())),
+            vec!["--cookies", "/home/me/cookies.txt"]
+        );
+        assert!(args_for(&AgePolicy::Anonymous).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skipped_videos_fail() {
+        use crate::fake::{self, Script};
+        use crate::progress::Progress;
+
+        let skipped = r#"[download] Skipping "Restricted" because it is age restricted"#;
+        let fakes = fake::install();
+        fakes.youtube_dl(Script::new().stdout(skipped));
+        let err =
+            crate::stream_fn("https://youtube.com/watch?v=phosphorus18", "out.mp3").unwrap_err();
+        assert!(err.to_string().contains("because it is age restricted"));
+        // `-g` implies `--quiet`, the skip usually isn't printed
+        fakes.youtube_dl(Script::new());
+        let err =
+            crate::stream_fn("https://youtube.com/watch?v=phosphorus18", "out.mp3").unwrap_err();
+        assert!(err.to_string().contains("skipped the video"));
+        fakes.youtube_dl(
+            Script::new()
+                .stderr("ERROR: [youtube] phosphorus18: Video unavailable")
+                .exit(1),
+        );
+        let err =
+            crate::stream_fn("https://youtube.com/watch?v=phosphorus18", "out.mp3").unwrap_err();
+        assert!(err.to_string().contains("Video unavailable"));
+
+        fakes.yt_dlp(Script::new().stdout(skipped));
+        let transfer =
+            crate::progress::download("https://youtube.com/watch?v=phosphorus18", "out").unwrap();
+        assert_eq!(
+            transfer.events.iter().last(),
+            Some(Progress::Failed(skipped.into()))
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Analysis of the beginning and the end of a track, so the host can schedule
+//! crossfades correctly between tracks coming from different plugins.
+//!
+//! Only a short window of audio is fetched and decoded, which makes the
+//! analysis cheap enough to run while prefetching the next track.
+
+use crate::{binaries, concurrency, events};
+use std::{error::Error, process::Stdio, time::Duration};
+
+/// Volume under which audio counts as silence
+const SILENCE_NOISE: &'static str = "-50dB";
+/// Shortest gap that counts as silence, in seconds
+const SILENCE_DURATION: &'static str = "0.3";
+/// Threshold under which a timestamp counts as the start of the window, in
+/// seconds
+const WINDOW_START_TOLERANCE: f32 = 0.05;
+
+/// Part of the track that is analyzed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// The first `Duration` of the track
+    Head(Duration),
+    /// The last `Duration` of the track
+    Tail(Duration),
+}
+
+/// Result of the analysis of a window
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// Silence at the beginning of a `Head` window
+    pub leading_silence: Duration,
+    /// Silence at the end of a `Tail` window
+    pub trailing_silence: Duration,
+    /// Integrated loudness of the window, in LUFS
+    pub loudness: Option<f32>,
+}
+
+/// Analyzes `window` of the track at `url`, a YouTube watch url
+pub fn analyze(url: &str, window: Window) -> Result<Analysis, Box<dyn Error>> {
+    let audio_url = crate::resolve_audio_url(url)?;
+
+    let mut command = binaries::ffmpeg();
+    command.args(["-hide_banner", "-nostats"]);
+    let length = match window {
+        Window::Head(length) => {
+            command.args(["-t", &length.as_secs_f32().to_string()]);
+            length
+        }
+        Window::Tail(length) => {
+            command.args(["-sseof", &format!("-{}", length.as_secs_f32())]);
+            length
+        }
+    };
+    command.args(["-i", &audio_url]);
+    command.args([
+        "-af",
+        &format!(
+            "silencedetect=noise={}:d={},ebur128",
+            SILENCE_NOISE, SILENCE_DURATION
+        ),
+    ]);
+    command.args(["-f", "null", "-"]);
+
+    // ffmpeg reports filter results on stderr
+    let _permit = concurrency::acquire()?;
+    let output = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| events::spawn_error("ffmpeg", err))?;
+    let report = String::from_utf8_lossy(&output.stderr);
+
+    Ok(parse_report(&report, window, length))
+}
+
+/// Reads the `silencedetect` and `ebur128` reports out of ffmpeg's output
+fn parse_report(report: &str, window: Window, length: Duration) -> Analysis {
+    let mut silences: Vec<(f32, Option<f32>)> = vec![];
+    let mut loudness = None;
+    let mut in_summary = false;
+
+    for line in report.lines() {
+        if let Some(start) = value_after(line, "silence_start:") {
+            silences.push((start, None));
+        } else if let Some(end) = value_after(line, "silence_end:") {
+            if let Some(silence) = silences.last_mut() {
+                silence.1 = Some(end);
+            }
+        } else if line.contains("Integrated loudness:") {
+            in_summary = true;
+        } else if in_summary {
+            if let Some(value) = value_after(line, "I:") {
+                loudness = Some(value);
+                in_summary = false;
+            }
+        }
+    }
+
+    let leading_silence = match (window, silences.first()) {
+        (Window::Head(_), Some((start, end))) if *start < WINDOW_START_TOLERANCE => {
+            end.unwrap_or(length.as_secs_f32())
+        }
+        _ => 0.0,
+    };
+    let trailing_silence = match (window, silences.last()) {
+        (Window::Tail(_), Some((start, None))) => (length.as_secs_f32() - start).max(0.0),
+        _ => 0.0,
+    };
+
+    Analysis {
+        leading_silence: Duration::from_secs_f32(leading_silence),
+        trailing_silence: Duration::from_secs_f32(trailing_silence),
+        loudness,
+    }
+}
+
+/// Parses the number that follows `key` in `line`
+fn value_after(line: &str, key: &str) -> Option<f32> {
+    let (_, rest) = line.split_once(key)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_report, Window};
+    use std::time::Duration;
+
+    const HEAD_REPORT: &'static str = "\
+[silencedetect @ 0x55d0c8] silence_start: 0
+[silencedetect @ 0x55d0c8] silence_end: 1.52 | silence_duration: 1.52
+[Parsed_ebur128_1 @ 0x55d0c9] Summary:
+
+  Integrated loudness:
+    I:         -13.8 LUFS
+    Threshold: -24.1 LUFS
+";
+
+    const TAIL_REPORT: &'static str = "\
+[silencedetect @ 0x55d0c8] silence_start: 12.1
+[silencedetect @ 0x55d0c8] silence_end: 12.6 | silence_duration: 0.5
+[silencedetect @ 0x55d0c8] silence_start: 26.5
+[Parsed_ebur128_1 @ 0x55d0c9] Summary:
+
+  Integrated loudness:
+    I:         -16.0 LUFS
+    Threshold: -26.3 LUFS
+";
+
+    #[test]
+    fn head_report() {
+        let length = Duration::from_secs(30);
+        let analysis = parse_report(HEAD_REPORT, Window::Head(length), length);
+
+        assert_eq!(analysis.leading_silence, Duration::from_secs_f32(1.52));
+        assert_eq!(analysis.trailing_silence, Duration::ZERO);
+        assert_eq!(analysis.loudness, Some(-13.8));
+    }
+
+    #[test]
+    fn tail_report() {
+        let length = Duration::from_secs(30);
+        let analysis = parse_report(TAIL_REPORT, Window::Tail(length), length);
+
+        assert_eq!(analysis.leading_silence, Duration::ZERO);
+        assert_eq!(analysis.trailing_silence, Duration::from_secs_f32(3.5));
+        assert_eq!(analysis.loudness, Some(-16.0));
+    }
+}
@@ -0,0 +1,103 @@
+//! Artwork sizes available for a video and selection of the best one for a
+//! given display size.
+
+use crate::YTItemParser;
+use serde_json::Value;
+use std::error::Error;
+
+const THUMBNAIL_HOST: &'static str = "https://i.ytimg.com/vi";
+
+/// Named thumbnails YouTube generates for every video, with their sizes.
+/// `maxresdefault` is only generated for HD uploads.
+const STOCK_SIZES: [(&'static str, u32, u32); 5] = [
+    ("default", 120, 90),
+    ("mqdefault", 320, 180),
+    ("hqdefault", 480, 360),
+    ("sddefault", 640, 480),
+    ("maxresdefault", 1280, 720),
+];
+
+/// A single artwork image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Artwork {
+    pub width: u32,
+    pub height: u32,
+    pub url: reqwest::Url,
+}
+
+/// Returns the stock artwork set of the video identified by `track_id`,
+/// ordered from the smallest to the largest.
+pub fn artwork_sizes(track_id: &str) -> Vec<Artwork> {
+    STOCK_SIZES
+        .iter()
+        .filter_map(|(name, width, height)| {
+            let url = format!("{}/{}/{}.jpg", THUMBNAIL_HOST, track_id, name);
+            Some(Artwork {
+                width: *width,
+                height: *height,
+                url: reqwest::Url::parse(&url).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Picks the smallest artwork that covers a `width`x`height` area. If none is
+/// big enough, the largest one is returned.
+pub fn best_for(sizes: &[Artwork], width: u32, height: u32) -> Option<&Artwork> {
+    let area = |artwork: &&Artwork| artwork.width as u64 * artwork.height as u64;
+
+    sizes
+        .iter()
+        .filter(|artwork| artwork.width >= width && artwork.height >= height)
+        .min_by_key(area)
+        .or_else(|| sizes.iter().max_by_key(area))
+}
+
+/// Reads every entry of a `thumbnails` array found in YouTube responses
+pub(crate) fn read_sizes(thumbnails: &Value) -> Result<Vec<Artwork>, Box<dyn Error>> {
+    let mut sizes = vec![];
+    for thumbnail in thumbnails.as_array().into_iter().flatten() {
+        let url = thumbnail.read("url")?.as_str().unwrap_or_default();
+        // Channel thumbnails are sometimes protocol-relative
+        let url = match url.strip_prefix("//") {
+            Some(url) => reqwest::Url::parse(&format!("https://{}", url))?,
+            None => reqwest::Url::parse(url)?,
+        };
+
+        sizes.push(Artwork {
+            width: thumbnail.get("width").and_then(Value::as_u64).unwrap_or(0) as u32,
+            height: thumbnail.get("height").and_then(Value::as_u64).unwrap_or(0) as u32,
+            url,
+        });
+    }
+
+    Ok(sizes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{artwork_sizes, best_for};
+
+    #[test]
+    fn best_size_selection() {
+        let sizes = artwork_sizes("dQw4w9WgXcQ");
+
+        let small = best_for(&sizes, 100, 60).unwrap();
+        assert_eq!(
+            small.url.as_str(),
+            "https://i.ytimg.com/vi/dQw4w9WgXcQ/default.jpg"
+        );
+
+        let list = best_for(&sizes, 300, 169).unwrap();
+        assert_eq!((list.width, list.height), (320, 180));
+
+        let huge = best_for(&sizes, 4000, 4000).unwrap();
+        assert_eq!(
+            (huge.width, huge.height),
+            (1280, 720),
+            "Falls back to the largest"
+        );
+
+        assert_eq!(best_for(&[], 10, 10), None);
+    }
+}
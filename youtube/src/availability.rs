@@ -0,0 +1,162 @@
+//! Cheap pre-playback check telling whether a video can actually be played.
+//!
+//! The probe asks the InnerTube `player` endpoint for the playability status of
+//! a video, which is a single small JSON request, so the host can flag
+//! geo-blocked or removed results in its UI instead of failing on play.
+//!
+//! The status doesn't tell geo-blocking apart from other unplayable videos,
+//! only its messages do. They're asked in English, whatever the languages set
+//! with `locale`, while the region is kept since it decides the blocking.
+
+use crate::{innertube, locale, YTItemParser};
+use serde_json::{json, Value};
+use std::error::Error;
+
+/// Language of the messages read by [`from_player_response`]
+const REASON_LANGUAGE: &'static str = "en";
+
+/// Playability of a single video, as reported by YouTube
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Availability {
+    /// The video can be played from the current region
+    Playable,
+    /// The video exists but isn't available in the current region
+    GeoBlocked(String),
+    /// The video requires a signed-in user (age-restricted, members only, ...)
+    LoginRequired(String),
+    /// The video has been removed, made private or never existed
+    Unavailable(String),
+}
+
+impl Availability {
+    /// Returns `true` if the video can be played
+    pub fn is_playable(&self) -> bool {
+        *self == Availability::Playable
+    }
+}
+
+/// Probes the availability of the video identified by `track_id`, the same id
+/// used to build `QueryResultData` instances.
+pub async fn probe(track_id: &str) -> Result<Availability, Box<dyn Error>> {
+    let body = probe_body(track_id, locale::client_locale());
+    let (json, _) = innertube::post(innertube::PLAYER_URL, &body).await?;
+
+    from_player_response(&json)
+}
+
+/// Body of the `player` request, in the region of `locale` but in English
+fn probe_body(track_id: &str, locale: (Option<String>, Option<String>)) -> Value {
+    let (_, region) = locale;
+    innertube::request_body_for(
+        json!({ "videoId": track_id }),
+        (Some(REASON_LANGUAGE.into()), region),
+    )
+}
+
+/// Reads the `playabilityStatus` object of a `player` response. Geo-blocked
+/// videos may only say so in the subreason of their error screen.
+fn from_player_response(json: &Value) -> Result<Availability, Box<dyn Error>> {
+    let status = json.read("playabilityStatus")?;
+    let reason = status
+        .get("reason")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let subreason = status
+        .pointer("/errorScreen/playerErrorMessageRenderer/subreason")
+        .map(text)
+        .unwrap_or_default();
+
+    let availability = match status.read("status")?.as_str().unwrap_or_default() {
+        "OK" => Availability::Playable,
+        "LOGIN_REQUIRED" | "AGE_CHECK_REQUIRED" | "CONTENT_CHECK_REQUIRED" => {
+            Availability::LoginRequired(reason)
+        }
+        "UNPLAYABLE" if reason.to_lowercase().contains("country") => {
+            Availability::GeoBlocked(reason)
+        }
+        "UNPLAYABLE" if subreason.to_lowercase().contains("country") => {
+            Availability::GeoBlocked(subreason)
+        }
+        _ => Availability::Unavailable(reason),
+    };
+
+    Ok(availability)
+}
+
+/// Text of a `simpleText` or `runs` message
+fn text(message: &Value) -> String {
+    if let Some(text) = message.get("simpleText").and_then(Value::as_str) {
+        return text.to_string();
+    }
+    message
+        .get("runs")
+        .and_then(Value::as_array)
+        .map(|runs| {
+            runs.iter()
+                .filter_map(|run| run.get("text").and_then(Value::as_str))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_player_response, probe_body, Availability};
+
+    #[test]
+    fn playability_status() {
+        let cases = [
+            (
+                r#"{"playabilityStatus":{"status":"OK"}}"#,
+                Availability::Playable,
+            ),
+            (
+                r#"{"playabilityStatus":{"status":"UNPLAYABLE","reason":"The uploader has not made this video available in your country"}}"#,
+                Availability::GeoBlocked(
+                    "The uploader has not made this video available in your country".into(),
+                ),
+            ),
+            (
+                r#"{"playabilityStatus":{"status":"UNPLAYABLE","reason":"Video unavailable","errorScreen":{"playerErrorMessageRenderer":{"subreason":{"runs":[{"text":"The uploader has not made this video available in your country"}]}}}}}"#,
+                Availability::GeoBlocked(
+                    "The uploader has not made this video available in your country".into(),
+                ),
+            ),
+            (
+                r#"{"playabilityStatus":{"status":"ERROR","reason":"Video unavailable"}}"#,
+                Availability::Unavailable("Video unavailable".into()),
+            ),
+            (
+                r#"{"playabilityStatus":{"status":"LOGIN_REQUIRED","reason":"Sign in to confirm your age"}}"#,
+                Availability::LoginRequired("Sign in to confirm your age".into()),
+            ),
+        ];
+
+        for (text, expected) in cases {
+            let json = serde_json::from_str(text).unwrap();
+            assert_eq!(from_player_response(&json).unwrap(), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn reasons_are_asked_in_english() {
+        let body = probe_body("dQw4w9WgXcQ", (Some("fr-FR".into()), Some("FR".into())));
+        assert_eq!(body["videoId"], "dQw4w9WgXcQ");
+        assert_eq!(body["context"]["client"]["hl"], "en");
+        assert_eq!(
+            body["context"]["client"]["gl"], "FR",
+            "The region decides the blocking"
+        );
+
+        // What a French client would be answered, without the English reason
+        let json = serde_json::json!({"playabilityStatus": {
+            "status": "UNPLAYABLE",
+            "reason": "Vidéo non disponible",
+        }});
+        assert_eq!(
+            from_player_response(&json).unwrap(),
+            Availability::Unavailable("Vidéo non disponible".into())
+        );
+    }
+}
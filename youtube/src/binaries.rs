@@ -0,0 +1,52 @@
+//! Paths of the binaries the plugin runs.
+//!
+//! By default `yt-dlp`, `youtube-dl` and `ffmpeg` are looked up in `PATH`.
+//! [`set_binaries`] points the plugins to other executables, like a bundled
+//! copy or, in tests, a fake that prints scripted output. The host should
+//! call [`probe`] when loading the plugin, to report missing binaries before
+//! the first download.
+
+use crate::events::{self, Event};
+pub use plugin_tools::binaries::{binaries, set_binaries, Binaries, MissingDependency};
+pub(crate) use plugin_tools::binaries::{ffmpeg, youtube_dl, yt_dlp};
+
+/// Checks that every binary can be run, emitting `ExtractorMissing` for the
+/// ones that can't. Returns the first one missing.
+pub fn probe() -> Result<(), MissingDependency> {
+    let checks = [
+        (yt_dlp(), "yt-dlp", "--version"),
+        (youtube_dl(), "youtube-dl", "--version"),
+        (ffmpeg(), "ffmpeg", "-version"),
+    ];
+
+    let mut missing = None;
+    for (command, name, version_flag) in checks {
+        if let Err(err) = plugin_tools::binaries::check(command, name, version_flag) {
+            events::emit(Event::ExtractorMissing(name.into()));
+            missing.get_or_insert(err);
+        }
+    }
+
+    match missing {
+        Some(missing) => Err(missing),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{binaries, probe, set_binaries, Binaries, MissingDependency};
+
+    #[cfg(unix)]
+    #[test]
+    fn dependency_probing() {
+        let _fakes = crate::fake::install();
+        assert_eq!(probe(), Ok(()));
+
+        set_binaries(Binaries {
+            yt_dlp: Some("/nonexistent/yt-dlp".into()),
+            ..binaries()
+        });
+        assert_eq!(probe(), Err(MissingDependency("yt-dlp".into())));
+    }
+}
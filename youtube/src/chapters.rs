@@ -0,0 +1,160 @@
+//! Chapters read from the tracklist of a video description.
+//!
+//! Long uploads (full albums, mixes, DJ sets) rarely have official chapters,
+//! but their description usually lists the start time of every track. Those
+//! tracklists are turned into [`Chapter`]s, that the host can use to skip
+//! between tracks, or to download every track apart with [`Chapter::clip_url`].
+//! Pinned comments aren't read: they need a signed-in session to be listed
+//! reliably.
+
+use crate::{download, innertube, YTItemParser};
+use regex::Regex;
+use serde_json::json;
+use std::{error::Error, time::Duration};
+
+/// Timestamps such as `3:45` or `1:02:03`
+const TIMESTAMP: &'static str = r"\b(?:(\d{1,2}):)?(\d{1,2}):(\d{2})\b";
+/// Track number before the title of a track, such as `1.` or `01)`
+const TRACK_NUMBER: &'static str = r"^\s*\d{1,3}[.)]\s";
+/// Separators between the timestamp and the title of a track. Brackets are
+/// kept, they may close the title, as in `Song (feat. Someone)`.
+const SEPARATORS: &'static str = "-–—|: \t";
+
+/// A track of a long upload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    pub start: Duration,
+    /// Start of the following chapter, or the end of the video if known
+    pub end: Option<Duration>,
+}
+
+impl Chapter {
+    /// Returns `url` restricted to this chapter, for `download_fn`
+    pub fn clip_url(&self, url: &str) -> Result<String, Box<dyn Error>> {
+        download::clip_url(url, self.start, self.end)
+    }
+}
+
+/// Reads the tracklist of the video identified by `track_id`. Returns no
+/// chapter if the description has no tracklist.
+pub async fn tracklist(track_id: &str) -> Result<Vec<Chapter>, Box<dyn Error>> {
+    let body = innertube::request_body(json!({ "videoId": track_id }));
+    let (json, _) = innertube::post(innertube::PLAYER_URL, &body).await?;
+
+    let details = json.read("videoDetails")?;
+    let description = details.read_str("shortDescription").unwrap_or_default();
+    let length = details
+        .read_str("lengthSeconds")
+        .ok()
+        .and_then(|length| length.parse().ok())
+        .map(Duration::from_secs);
+
+    Ok(parse_tracklist(description, length))
+}
+
+/// Parses the tracklist in `text`, one track per line. `length` is the
+/// length of the video, ending the last chapter. Lines out of order are
+/// ignored, and fewer than two tracks aren't a tracklist.
+pub fn parse_tracklist(text: &str, length: Option<Duration>) -> Vec<Chapter> {
+    let timestamp = Regex::new(TIMESTAMP).unwrap();
+    // The timestamp, or the range, with the brackets around it
+    let stamp = Regex::new(&format!(r"[\[(]?{0}(?:\s*[-–—]\s*{0})?[\])]?", TIMESTAMP)).unwrap();
+    let track_number = Regex::new(TRACK_NUMBER).unwrap();
+
+    let mut chapters: Vec<Chapter> = vec![];
+    for line in text.lines() {
+        let start = match timestamp.captures(line) {
+            Some(captures) => {
+                let part = |index| {
+                    captures
+                        .get(index)
+                        .and_then(|part| part.as_str().parse::<u64>().ok())
+                        .unwrap_or(0)
+                };
+                Duration::from_secs(part(1) * 3600 + part(2) * 60 + part(3))
+            }
+            None => continue,
+        };
+        let out_of_order = matches!(chapters.last(), Some(last) if start <= last.start);
+        let past_the_end = matches!(length, Some(length) if start >= length);
+        if out_of_order || past_the_end {
+            continue;
+        }
+
+        // Ranges such as `0:00 - 3:45` list the end of the track as well
+        let title = stamp.replace_all(line, "");
+        let title = track_number
+            .replace(&title, "")
+            .trim_matches(|c: char| SEPARATORS.contains(c))
+            .to_string();
+        chapters.push(Chapter {
+            title: if title.is_empty() {
+                format!("Track {}", chapters.len() + 1)
+            } else {
+                title
+            },
+            start,
+            end: None,
+        });
+    }
+    if chapters.len() < 2 {
+        return vec![];
+    }
+
+    let starts: Vec<Duration> = chapters
+        .iter()
+        .skip(1)
+        .map(|chapter| chapter.start)
+        .collect();
+    for (chapter, end) in chapters
+        .iter_mut()
+        .zip(starts.into_iter().map(Some).chain([length]))
+    {
+        chapter.end = end;
+    }
+
+    chapters
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_tracklist, Chapter};
+    use std::time::Duration;
+
+    #[test]
+    fn description_tracklist() {
+        let description = "Full album, out now!\n\
+            Tracklist:\n\
+            00:00 Intro\n\
+            1. Nightcall - 3:12\n\
+            [07:31] Tick of the Clock\n\
+            12:40 - Say It (feat. Tove Lo)\n\
+            (1:02:03 - 1:05:00) Outro | feat. Someone\n\
+            Thanks for listening, see you at 3:00 am";
+        let chapters = parse_tracklist(description, Some(Duration::from_secs(3900)));
+        let titles: Vec<&str> = chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Intro",
+                "Nightcall",
+                "Tick of the Clock",
+                "Say It (feat. Tove Lo)",
+                "Outro | feat. Someone"
+            ]
+        );
+        assert_eq!(
+            chapters[1],
+            Chapter {
+                title: "Nightcall".into(),
+                start: Duration::from_secs(192),
+                end: Some(Duration::from_secs(451)),
+            }
+        );
+        assert_eq!(chapters[4].start, Duration::from_secs(3723));
+        assert_eq!(chapters[4].end, Some(Duration::from_secs(3900)));
+
+        assert!(parse_tracklist("Skip to 2:30 for the drop", None).is_empty());
+    }
+}
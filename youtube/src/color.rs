@@ -0,0 +1,117 @@
+//! Colors of the artwork, to theme the host per track.
+//!
+//! Needs the `color` feature. The host passes the artwork it already cached
+//! for a result, in any of the formats YouTube serves (JPEG, PNG, WebP).
+
+use image::{imageops::FilterType, GenericImageView};
+use std::{collections::HashMap, error::Error};
+
+/// Side of the image colors are picked from, after downscaling
+const SAMPLE_SIZE: u32 = 32;
+/// Bits kept per channel when grouping similar colors
+const QUANTIZATION_BITS: u8 = 4;
+/// Least saturation of an accent color, from 0 to 1
+const MIN_ACCENT_SATURATION: f32 = 0.3;
+
+/// A color, in sRGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Saturation, from 0 to 1, as in the HSV model
+    fn saturation(&self) -> f32 {
+        let max = self.0.max(self.1).max(self.2) as f32;
+        let min = self.0.min(self.1).min(self.2) as f32;
+        if max == 0.0 {
+            0.0
+        } else {
+            (max - min) / max
+        }
+    }
+}
+
+/// Colors picked from an artwork
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Color covering most of the artwork
+    pub dominant: Rgb,
+    /// Most common vivid color, or the dominant one for dull artworks
+    pub accent: Rgb,
+}
+
+/// Picks the colors of the encoded `artwork`
+pub fn palette(artwork: &[u8]) -> Result<Palette, Box<dyn Error>> {
+    let image = image::load_from_memory(artwork)?.resize_exact(
+        SAMPLE_SIZE,
+        SAMPLE_SIZE,
+        FilterType::Triangle,
+    );
+
+    // Pixels are grouped by their quantized color, keeping the sum of each
+    // channel to average the group
+    let shift = 8 - QUANTIZATION_BITS;
+    let mut groups: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+    for (_, _, pixel) in image.pixels() {
+        let [r, g, b, alpha] = pixel.0;
+        if alpha < 128 {
+            continue;
+        }
+        let group = groups
+            .entry((r >> shift, g >> shift, b >> shift))
+            .or_insert((0, [0; 3]));
+        group.0 += 1;
+        group.1[0] += r as u32;
+        group.1[1] += g as u32;
+        group.1[2] += b as u32;
+    }
+
+    let mut colors: Vec<(u32, Rgb)> = groups
+        .into_values()
+        .map(|(count, [r, g, b])| {
+            let color = Rgb((r / count) as u8, (g / count) as u8, (b / count) as u8);
+            (count, color)
+        })
+        .collect();
+    colors.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let dominant = match colors.first() {
+        Some((_, color)) => *color,
+        None => return Err("The artwork is transparent".into()),
+    };
+    let accent = colors
+        .iter()
+        .map(|(_, color)| *color)
+        .find(|color| *color != dominant && color.saturation() >= MIN_ACCENT_SATURATION)
+        .unwrap_or(dominant);
+
+    Ok(Palette { dominant, accent })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{palette, Rgb};
+    use image::{ImageOutputFormat, Rgb as Pixel, RgbImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn dominant_and_accent() {
+        // Mostly dark grey, with an orange stripe
+        let image = RgbImage::from_fn(64, 64, |_, y| {
+            if y < 16 {
+                Pixel([240, 120, 0])
+            } else {
+                Pixel([40, 40, 40])
+            }
+        });
+        let mut artwork = Cursor::new(vec![]);
+        image
+            .write_to(&mut artwork, ImageOutputFormat::Png)
+            .unwrap();
+
+        let palette = palette(artwork.get_ref()).unwrap();
+        assert_eq!(palette.dominant, Rgb(40, 40, 40));
+        assert_eq!(palette.accent, Rgb(240, 120, 0));
+
+        assert!(super::palette(b"not an image").is_err());
+    }
+}
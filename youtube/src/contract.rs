@@ -0,0 +1,23 @@
+//! Contract tests towards phosphorus_core, see `plugin_contract`.
+
+use crate::YouTube;
+use std::path::Path;
+
+#[test]
+fn plugin_contract() {
+    let plugin = YouTube::new();
+    plugin_contract::check_plugin(&plugin);
+
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    // Playlists are fetched page by page, their parsing is tested by `playlist`
+    let fixtures: Vec<_> = plugin_contract::fixtures(&tests.join("corpus"))
+        .into_iter()
+        .filter(|fixture| !fixture.ends_with("2022-10-playlist.json"))
+        .collect();
+    plugin_contract::check_search(
+        &plugin,
+        "never gonna give you up",
+        &fixtures,
+        &tests.join("snapshots"),
+    );
+}
@@ -0,0 +1,143 @@
+//! Options applied to the downloads started by `download_fn`.
+//!
+//! Options shared by every download are set with [`set_options`], for every
+//! plugin at once. A single download can be clipped to a time range by
+//! passing the url returned by [`clip_url`] to `download_fn`. The binaries
+//! run are set apart, with `binaries::set_binaries`.
+
+pub(crate) use plugin_tools::download::{extractor_args, output_args};
+pub use plugin_tools::download::{options, set_options, AudioFormat, DownloadOptions};
+use std::{error::Error, time::Duration};
+
+/// Query parameters holding the clip range, in seconds, as in embed urls
+const START_PARAM: &'static str = "start";
+const END_PARAM: &'static str = "end";
+
+/// Returns `url` restricted to the range from `start` to `end` (or to the end
+/// of the media). Only that part is downloaded. Ranges ending before they
+/// start, to the second, are refused.
+pub fn clip_url(
+    url: &str,
+    start: Duration,
+    end: Option<Duration>,
+) -> Result<String, Box<dyn Error>> {
+    if let Some(end) = end {
+        if end.as_secs() <= start.as_secs() {
+            return Err(format!(
+                "Clip range {}s-{}s ends before it starts",
+                start.as_secs(),
+                end.as_secs()
+            )
+            .into());
+        }
+    }
+    let mut url = reqwest::Url::parse(url)?;
+    url.query_pairs_mut()
+        .append_pair(START_PARAM, &start.as_secs().to_string());
+    if let Some(end) = end {
+        url.query_pairs_mut()
+            .append_pair(END_PARAM, &end.as_secs().to_string());
+    }
+
+    Ok(url.into())
+}
+
+/// Splits the clip range out of `url`. Returns the url without it and the
+/// `yt-dlp` arguments downloading only that range.
+pub(crate) fn clip_args(url: &str) -> (String, Vec<String>) {
+    let mut parsed = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return (url.into(), vec![]),
+    };
+
+    let (mut start, mut end) = (None, None);
+    let pairs: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, value)| match key.as_ref() {
+            START_PARAM => {
+                start = value.parse::<u64>().ok();
+                false
+            }
+            END_PARAM => {
+                end = value.parse::<u64>().ok();
+                false
+            }
+            _ => true,
+        })
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    if start.is_none() && end.is_none() {
+        return (url.into(), vec![]);
+    }
+
+    parsed.query_pairs_mut().clear().extend_pairs(pairs);
+    let section = format!(
+        "*{}-{}",
+        start.unwrap_or(0),
+        end.map(|end| end.to_string()).unwrap_or("inf".into())
+    );
+    let args = vec![
+        "--download-sections".into(),
+        section,
+        // Cuts exactly at the requested times instead of the closest keyframe
+        "--force-keyframes-at-cuts".into(),
+    ];
+
+    (parsed.into(), args)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{clip_args, clip_url};
+    use std::time::Duration;
+
+    #[test]
+    fn clip_range() {
+        let url = clip_url(
+            "https://youtube.com/watch?v=dQw4w9WgXcQ",
+            Duration::from_secs(90),
+            Some(Duration::from_secs(300)),
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "https://youtube.com/watch?v=dQw4w9WgXcQ&start=90&end=300"
+        );
+
+        let (source, args) = clip_args(&url);
+        assert_eq!(source, "https://youtube.com/watch?v=dQw4w9WgXcQ");
+        assert_eq!(
+            args,
+            vec![
+                "--download-sections",
+                "*90-300",
+                "--force-keyframes-at-cuts"
+            ]
+        );
+
+        let url = clip_url(
+            "https://youtube.com/watch?v=dQw4w9WgXcQ",
+            Duration::from_secs(60),
+            None,
+        )
+        .unwrap();
+        assert_eq!(clip_args(&url).1[1], "*60-inf");
+
+        for end in [30, 60] {
+            assert!(clip_url(
+                "https://youtube.com/watch?v=dQw4w9WgXcQ",
+                Duration::from_secs(60),
+                Some(Duration::from_secs(end)),
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn unclipped_url() {
+        let (source, args) = clip_args("https://youtube.com/watch?v=dQw4w9WgXcQ");
+        assert_eq!(source, "https://youtube.com/watch?v=dQw4w9WgXcQ");
+        assert!(args.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+//! Notifications the host can subscribe to, instead of polling process handles
+//! or inspecting errors to find out why an operation failed.
+
+use crate::{binaries::MissingDependency, trace::Bundle};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// Event emitted by the plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// YouTube refused to answer (rate limiting, consent wall, bot check)
+    Blocked(String),
+    /// A binary needed to download or stream couldn't be found
+    ExtractorMissing(String),
+    /// Searches failed repeatedly, they're refused for the given time
+    Disabled(std::time::Duration),
+    /// A search succeeded after the plugin was disabled
+    Recovered,
+    /// An operation succeeded, but degraded: results were skipped, a
+    /// playlist was truncated, an option was overridden
+    Warning(String),
+    /// The download of the url started by `progress::download` finished
+    DownloadFinished(String),
+    /// The download of the url started by `progress::download` failed, for
+    /// the given reason
+    DownloadFailed(String, String),
+}
+
+/// Registered listener, see [`unsubscribe`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Subscription(u64);
+
+type Listener = Arc<dyn Fn(&Event) + Send + Sync>;
+
+static LISTENERS: Mutex<Vec<(u64, Listener)>> = Mutex::new(Vec::new());
+static NEXT_SUBSCRIPTION: AtomicU64 = AtomicU64::new(0);
+
+/// Registers `listener`, that will be called for every event emitted from now
+/// on. Listeners are called on the thread that emits the event, so they should
+/// return quickly. They may subscribe and emit themselves.
+pub fn subscribe(listener: impl Fn(&Event) + Send + Sync + 'static) -> Subscription {
+    let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed);
+    LISTENERS.lock().unwrap().push((id, Arc::new(listener)));
+    Subscription(id)
+}
+
+/// Removes the listener registered with `subscription`
+pub fn unsubscribe(subscription: Subscription) {
+    LISTENERS
+        .lock()
+        .unwrap()
+        .retain(|(id, _)| *id != subscription.0);
+}
+
+/// Removes every registered listener
+pub fn clear() {
+    LISTENERS.lock().unwrap().clear();
+}
+
+pub(crate) fn emit(event: Event) {
+    // Called without the lock, a listener may subscribe or emit
+    let listeners: Vec<Listener> = LISTENERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, listener)| listener.clone())
+        .collect();
+    for listener in listeners {
+        listener(&event);
+    }
+}
+
+/// Emits `message` as a `Warning`
+pub(crate) fn warn(message: String) {
+    emit(Event::Warning(message));
+}
+
+/// Emits `message` as a `Warning`, recording it in `bundle` as well
+pub(crate) fn warn_traced(bundle: &Bundle, message: String) {
+    bundle.decision(&format!("Warning: {}", message));
+    emit(Event::Warning(message));
+}
+
+/// Maps an error spawning `binary`, emitting `ExtractorMissing` and returning
+/// a `MissingDependency` if the binary doesn't exist.
+pub(crate) fn spawn_error(binary: &str, err: std::io::Error) -> Box<dyn std::error::Error> {
+    let err = plugin_tools::binaries::spawn_error(binary, err);
+    if err.is::<MissingDependency>() {
+        emit(Event::ExtractorMissing(binary.into()));
+    }
+
+    err
+}
+
+#[cfg(test)]
+mod test {
+    use super::{emit, spawn_error, subscribe, unsubscribe, warn, Event, MissingDependency};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn missing_binary_is_notified() {
+        let received = Arc::new(Mutex::new(vec![]));
+        let sink = received.clone();
+        // Other tests may emit events meanwhile
+        let subscription = subscribe(move |event| match event {
+            Event::ExtractorMissing(binary) if binary.starts_with("phosphorus-") => {
+                sink.lock().unwrap().push(event.clone())
+            }
+            Event::Warning(message) if message.starts_with("phosphorus-") => {
+                sink.lock().unwrap().push(event.clone())
+            }
+            Event::Blocked(reason) if reason == "phosphorus-nested" => {
+                // Listeners run without the lock
+                let _ = subscribe(|_| ());
+                emit(Event::Warning("phosphorus-nested".into()));
+            }
+            _ => (),
+        });
+
+        let err = std::process::Command::new("phosphorus-missing-binary")
+            .spawn()
+            .map_err(|err| spawn_error("phosphorus-missing-binary", err));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            MissingDependency("phosphorus-missing-binary".into()).to_string()
+        );
+        warn("phosphorus-test degraded".into());
+        emit(Event::Blocked("phosphorus-nested".into()));
+        unsubscribe(subscription);
+        warn("phosphorus-unsubscribed".into());
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                Event::ExtractorMissing("phosphorus-missing-binary".into()),
+                Event::Warning("phosphorus-test degraded".into()),
+                Event::Warning("phosphorus-nested".into())
+            ]
+        );
+    }
+}
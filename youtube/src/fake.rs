@@ -0,0 +1,127 @@
+//! Fake binaries for the tests of the download and stream pipelines.
+//!
+//! [`install`] replaces `yt-dlp`, `youtube-dl` and `ffmpeg` with shell scripts
+//! built from a [`Script`], so the pipelines run without network, extractor or
+//! real `ffmpeg`. Each fake records the arguments it was run with. The
+//! binaries are a global setting, so tests using fakes run one at a time.
+
+use crate::binaries::{self, Binaries};
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Behaviour of a fake binary, run step by step
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Script {
+    steps: Vec<String>,
+}
+
+impl Script {
+    pub(crate) fn new() -> Self {
+        Script::default()
+    }
+
+    /// Prints `line` on stdout
+    pub(crate) fn stdout(mut self, line: &str) -> Self {
+        self.steps.push(format!("printf '%s\\n' {}", quote(line)));
+        self
+    }
+
+    /// Prints `line` on stderr
+    pub(crate) fn stderr(mut self, line: &str) -> Self {
+        self.steps
+            .push(format!("printf '%s\\n' {} >&2", quote(line)));
+        self
+    }
+
+    /// Blocks until killed, in a child process like extractors do
+    pub(crate) fn hang(mut self) -> Self {
+        self.steps.push("sleep 60".into());
+        self
+    }
+
+    /// Exits with `code`
+    pub(crate) fn exit(mut self, code: i32) -> Self {
+        self.steps.push(format!("exit {}", code));
+        self
+    }
+}
+
+/// Fake binaries installed until dropped
+pub(crate) struct Fakes {
+    dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+/// Installs fakes that exit successfully without output, until replaced with
+/// [`Fakes::yt_dlp`], [`Fakes::youtube_dl`] or [`Fakes::ffmpeg`]
+pub(crate) fn install() -> Fakes {
+    let lock = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let dir = std::env::temp_dir().join(format!("phosphorus-fakes-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let fakes = Fakes { dir, _lock: lock };
+    binaries::set_binaries(Binaries {
+        yt_dlp: Some(fakes.write("yt-dlp", &Script::new())),
+        youtube_dl: Some(fakes.write("youtube-dl", &Script::new())),
+        ffmpeg: Some(fakes.write("ffmpeg", &Script::new())),
+    });
+
+    fakes
+}
+
+impl Fakes {
+    pub(crate) fn yt_dlp(&self, script: Script) -> &Self {
+        self.write("yt-dlp", &script);
+        self
+    }
+
+    pub(crate) fn youtube_dl(&self, script: Script) -> &Self {
+        self.write("youtube-dl", &script);
+        self
+    }
+
+    pub(crate) fn ffmpeg(&self, script: Script) -> &Self {
+        self.write("ffmpeg", &script);
+        self
+    }
+
+    /// Arguments the fake `name` was last run with
+    pub(crate) fn args(&self, name: &str) -> Vec<String> {
+        match fs::read_to_string(self.dir.join(format!("{}.args", name))) {
+            Ok(args) => args.lines().map(String::from).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn write(&self, name: &str, script: &Script) -> PathBuf {
+        let path = self.dir.join(name);
+        let record = self.dir.join(format!("{}.args", name));
+        let text = format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > {}\n{}\n",
+            quote(&record.to_string_lossy()),
+            script.steps.join("\n")
+        );
+        fs::write(&path, text).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        path
+    }
+}
+
+impl Drop for Fakes {
+    fn drop(&mut self) {
+        binaries::set_binaries(Binaries::default());
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Quotes `text` for the shell
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
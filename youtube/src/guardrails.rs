@@ -0,0 +1,237 @@
+//! Limits enforced on downloads before they start, for shared machines and
+//! limited data plans.
+//!
+//! Size, duration and channel limits are handed over to the extractor, which
+//! rejects the video before fetching it. The extractor still exits with
+//! success then, so `progress::download` reads its output and reports the
+//! download as failed. The daily limit is tracked here and refuses to start
+//! new downloads once reached. It counts downloads rather than their volume,
+//! which is only known once they're over: combined with `max_file_size`, it
+//! bounds the volume downloaded per day. A download is only counted once its
+//! process is started, so refused or failed spawns don't use up the limit.
+
+use std::{
+    fmt,
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Limits applied to every download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Guardrails {
+    /// Longest media that can be downloaded
+    pub max_duration: Option<Duration>,
+    /// Biggest file that can be downloaded, in bytes
+    pub max_file_size: Option<u64>,
+    /// Number of downloads that can be started per day, whatever their size
+    pub max_daily_downloads: Option<u32>,
+    /// Channels whose videos can't be downloaded
+    pub blocked_channels: Vec<String>,
+}
+
+impl Guardrails {
+    /// Guardrails that allow everything
+    pub const fn new() -> Self {
+        Guardrails {
+            max_duration: None,
+            max_file_size: None,
+            max_daily_downloads: None,
+            blocked_channels: Vec::new(),
+        }
+    }
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Guardrails::new()
+    }
+}
+
+/// Error returned when a download is refused by the guardrails
+#[derive(Debug)]
+pub enum GuardrailError {
+    DailyLimitReached(u32),
+}
+
+impl fmt::Display for GuardrailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardrailError::DailyLimitReached(limit) => {
+                write!(f, "Daily limit of {} downloads reached", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GuardrailError {}
+
+static GUARDRAILS: RwLock<Guardrails> = RwLock::new(Guardrails::new());
+static DAILY_COUNT: Mutex<DailyCount> = Mutex::new(DailyCount { day: 0, count: 0 });
+
+/// Replaces the guardrails applied to downloads
+pub fn set(guardrails: Guardrails) {
+    *GUARDRAILS.write().unwrap() = guardrails;
+}
+
+/// Returns the guardrails currently applied to downloads
+pub fn current() -> Guardrails {
+    GUARDRAILS.read().unwrap().clone()
+}
+
+/// Checks the daily limit. Returns the extractor arguments enforcing the
+/// remaining limits. The download is counted by [`count`], once started.
+pub(crate) fn admit() -> Result<Vec<String>, GuardrailError> {
+    let guardrails = GUARDRAILS.read().unwrap();
+    DAILY_COUNT
+        .lock()
+        .unwrap()
+        .check(guardrails.max_daily_downloads, today())?;
+
+    Ok(extractor_args(&guardrails))
+}
+
+/// Counts a download whose process was started
+pub(crate) fn count() {
+    DAILY_COUNT.lock().unwrap().add(today());
+}
+
+/// Days since the epoch
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / SECS_PER_DAY)
+        .unwrap_or_default()
+}
+
+/// Number of downloads started during `day`
+#[derive(Debug)]
+struct DailyCount {
+    day: u64,
+    count: u32,
+}
+
+impl DailyCount {
+    fn check(&mut self, limit: Option<u32>, today: u64) -> Result<(), GuardrailError> {
+        self.roll(today);
+        match limit {
+            Some(limit) if self.count >= limit => Err(GuardrailError::DailyLimitReached(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn add(&mut self, today: u64) {
+        self.roll(today);
+        self.count += 1;
+    }
+
+    fn roll(&mut self, today: u64) {
+        if self.day != today {
+            *self = DailyCount {
+                day: today,
+                count: 0,
+            };
+        }
+    }
+}
+
+/// Translates `guardrails` to `yt-dlp` arguments
+fn extractor_args(guardrails: &Guardrails) -> Vec<String> {
+    let mut args = vec![];
+    if let Some(size) = guardrails.max_file_size {
+        args.push("--max-filesize".into());
+        args.push(size.to_string());
+    }
+
+    let mut filters = vec![];
+    if let Some(duration) = guardrails.max_duration {
+        filters.push(format!("duration <= {}", duration.as_secs()));
+    }
+    for channel in &guardrails.blocked_channels {
+        filters.push(format!("channel != '{}'", escape(channel)));
+    }
+    if !filters.is_empty() {
+        args.push("--match-filter".into());
+        args.push(filters.join(" & "));
+    }
+
+    args
+}
+
+/// Escapes `value` for a quoted string of `--match-filter`, where `&`
+/// separates the filters
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('&', "\\&")
+}
+
+/// Reason the extractor gave for skipping a download because of the
+/// guardrails, if `line` is one
+pub(crate) fn refusal(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.contains("does not pass filter") || line.contains("larger than max-filesize") {
+        Some(line.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{extractor_args, refusal, DailyCount, Guardrails};
+    use std::time::Duration;
+
+    #[test]
+    fn extractor_arguments() {
+        assert!(extractor_args(&Guardrails::new()).is_empty());
+
+        let guardrails = Guardrails {
+            max_duration: Some(Duration::from_secs(600)),
+            max_file_size: Some(20_000_000),
+            max_daily_downloads: Some(10),
+            blocked_channels: vec!["Rick's Channel".into()],
+        };
+        assert_eq!(
+            extractor_args(&guardrails),
+            vec![
+                "--max-filesize",
+                "20000000",
+                "--match-filter",
+                r"duration <= 600 & channel != 'Rick\'s Channel'",
+            ]
+        );
+
+        let guardrails = Guardrails {
+            blocked_channels: vec![r"Tom & Jerry \ Friends".into()],
+            ..Guardrails::new()
+        };
+        assert_eq!(
+            extractor_args(&guardrails),
+            vec!["--match-filter", r"channel != 'Tom \& Jerry \\ Friends'"]
+        );
+
+        assert!(
+            refusal("[download] Never does not pass filter (duration <= 600), skipping ..")
+                .is_some()
+        );
+        assert!(refusal("[download] Destination: out.webm").is_none());
+    }
+
+    #[test]
+    fn only_started_downloads_count() {
+        let mut daily = DailyCount { day: 0, count: 0 };
+        assert!(daily.check(Some(1), 19000).is_ok());
+        assert!(
+            daily.check(Some(1), 19000).is_ok(),
+            "Admitted downloads that never started"
+        );
+
+        daily.add(19000);
+        assert!(daily.check(Some(1), 19000).is_err());
+        assert!(daily.check(None, 19000).is_ok());
+        assert!(daily.check(Some(1), 19001).is_ok(), "A new day");
+    }
+}
@@ -0,0 +1,341 @@
+//! Error budget of the searches.
+//!
+//! When YouTube blocks the plugin, every following search fails too, after
+//! waiting for the network. Once [`HealthPolicy::max_failures`] searches in a
+//! row fail, the plugin is disabled for [`HealthPolicy::cooldown`]: searches
+//! are refused at once, and `Event::Disabled` is emitted. After the cool-down,
+//! a single search is let through as a probe, the others are refused until it
+//! completes; `Event::Recovered` is emitted if it succeeds, otherwise the
+//! plugin is disabled again.
+//!
+//! The host sends the requests and only hands the responses over, so a search
+//! whose request times out or can't connect is never parsed. A search still
+//! not parsed after [`HealthPolicy::request_timeout`] counts as failed.
+
+use crate::events::{self, Event};
+use std::{
+    error::Error,
+    fmt,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+/// When the plugin is disabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthPolicy {
+    /// Failed searches in a row disabling the plugin
+    pub max_failures: u32,
+    /// Time the plugin stays disabled
+    pub cooldown: Duration,
+    /// Time after which a search that wasn't parsed counts as failed
+    pub request_timeout: Duration,
+}
+
+impl HealthPolicy {
+    const fn new() -> Self {
+        HealthPolicy {
+            max_failures: 3,
+            cooldown: Duration::from_secs(300),
+            request_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        HealthPolicy::new()
+    }
+}
+
+/// Health of the plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// The last searches failed, but fewer than `max_failures`
+    Degraded(u32),
+    /// Searches are refused for the time left
+    Disabled(Duration),
+}
+
+/// Error returned by searches while the plugin is disabled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisabledError {
+    /// Time left before searches are let through again, zero while a probe
+    /// search runs
+    pub retry_in: Duration,
+}
+
+impl fmt::Display for DisabledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "YouTube is disabled after repeated failures, retry in {}s",
+            self.retry_in.as_secs()
+        )
+    }
+}
+
+impl Error for DisabledError {}
+
+static POLICY: RwLock<HealthPolicy> = RwLock::new(HealthPolicy::new());
+static BREAKER: Mutex<Breaker> = Mutex::new(Breaker::new());
+
+/// Applies `policy` from now on
+pub fn set_policy(policy: HealthPolicy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+/// Returns the policy disabling the plugin
+pub fn policy() -> HealthPolicy {
+    *POLICY.read().unwrap()
+}
+
+/// Returns the health of the plugin
+pub fn health() -> Health {
+    let policy = policy();
+    let now = Instant::now();
+    let mut breaker = BREAKER.lock().unwrap();
+    let event = breaker.expire(&policy, now);
+    let health = breaker.health(&policy, now);
+    drop(breaker);
+    if let Some(event) = event {
+        events::emit(event);
+    }
+
+    health
+}
+
+/// Lets a search through, unless the plugin is disabled. Its outcome is
+/// expected by [`record`].
+pub(crate) fn admit() -> Result<(), DisabledError> {
+    let policy = policy();
+    let now = Instant::now();
+    let mut breaker = BREAKER.lock().unwrap();
+    let event = breaker.expire(&policy, now);
+    let admitted = breaker.admit(&policy, now);
+    drop(breaker);
+    if let Some(event) = event {
+        events::emit(event);
+    }
+
+    admitted
+}
+
+/// Records the outcome of a search
+pub(crate) fn record<T>(outcome: &Result<T, Box<dyn Error>>) {
+    let event = BREAKER
+        .lock()
+        .unwrap()
+        .record(outcome.is_ok(), &policy(), Instant::now());
+    if let Some(event) = event {
+        events::emit(event);
+    }
+}
+
+struct Breaker {
+    failures: u32,
+    disabled_until: Option<Instant>,
+    /// Start of the probe search let through after the cool-down
+    probe: Option<Instant>,
+    /// Start of the searches let through and not recorded yet, oldest first
+    searches: Vec<Instant>,
+}
+
+impl Breaker {
+    const fn new() -> Self {
+        Breaker {
+            failures: 0,
+            disabled_until: None,
+            probe: None,
+            searches: Vec::new(),
+        }
+    }
+
+    fn health(&self, policy: &HealthPolicy, now: Instant) -> Health {
+        match self.disabled_until {
+            Some(until) if until > now => Health::Disabled(until - now),
+            _ if self.failures == 0 => Health::Healthy,
+            _ => Health::Degraded(self.failures.min(policy.max_failures)),
+        }
+    }
+
+    fn admit(&mut self, policy: &HealthPolicy, now: Instant) -> Result<(), DisabledError> {
+        match (self.disabled_until, self.probe) {
+            (Some(until), _) if until > now => {
+                return Err(DisabledError {
+                    retry_in: until - now,
+                })
+            }
+            (Some(_), Some(probe)) if probe + policy.cooldown > now => {
+                return Err(DisabledError {
+                    retry_in: Duration::ZERO,
+                })
+            }
+            (Some(_), _) => self.probe = Some(now),
+            (None, _) => (),
+        }
+        self.searches.push(now);
+
+        Ok(())
+    }
+
+    /// Counts the searches not recorded within the request timeout as failed.
+    /// Returns the event to emit, if the health changed.
+    fn expire(&mut self, policy: &HealthPolicy, now: Instant) -> Option<Event> {
+        let lost = self
+            .searches
+            .iter()
+            .take_while(|start| **start + policy.request_timeout <= now)
+            .count();
+        self.searches.drain(..lost);
+
+        let mut event = None;
+        for _ in 0..lost {
+            event = self.outcome(false, policy, now).or(event);
+        }
+        event
+    }
+
+    /// Records the outcome of the oldest search. Returns the event to emit, if
+    /// the health changed.
+    fn record(&mut self, succeeded: bool, policy: &HealthPolicy, now: Instant) -> Option<Event> {
+        if !self.searches.is_empty() {
+            self.searches.remove(0);
+        }
+        self.outcome(succeeded, policy, now)
+    }
+
+    fn outcome(&mut self, succeeded: bool, policy: &HealthPolicy, now: Instant) -> Option<Event> {
+        self.probe = None;
+        if succeeded {
+            let recovered = self.disabled_until.take().is_some();
+            self.failures = 0;
+            return if recovered {
+                Some(Event::Recovered)
+            } else {
+                None
+            };
+        }
+
+        self.failures += 1;
+        if self.failures >= policy.max_failures.max(1) {
+            self.disabled_until = Some(now + policy.cooldown);
+            Some(Event::Disabled(policy.cooldown))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Breaker, Health, HealthPolicy};
+    use crate::events::Event;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn failures_disable_until_recovery() {
+        let policy = HealthPolicy {
+            max_failures: 2,
+            cooldown: Duration::from_secs(60),
+            ..HealthPolicy::default()
+        };
+        let mut breaker = Breaker::new();
+        let start = Instant::now();
+
+        assert_eq!(breaker.record(false, &policy, start), None);
+        assert_eq!(breaker.health(&policy, start), Health::Degraded(1));
+        assert_eq!(
+            breaker.record(false, &policy, start),
+            Some(Event::Disabled(Duration::from_secs(60)))
+        );
+        assert!(breaker
+            .admit(&policy, start + Duration::from_secs(30))
+            .is_err());
+
+        let later = start + Duration::from_secs(61);
+        assert!(
+            breaker.admit(&policy, later).is_ok(),
+            "Let through after the cool-down"
+        );
+        assert!(
+            breaker.admit(&policy, later).is_err(),
+            "A single search probes"
+        );
+        assert_eq!(
+            breaker.record(false, &policy, later),
+            Some(Event::Disabled(Duration::from_secs(60))),
+            "Disabled again at once"
+        );
+
+        let recovery = later + Duration::from_secs(61);
+        assert!(breaker.admit(&policy, recovery).is_ok());
+        assert_eq!(
+            breaker.record(true, &policy, recovery),
+            Some(Event::Recovered)
+        );
+        assert_eq!(breaker.health(&policy, recovery), Health::Healthy);
+    }
+
+    #[test]
+    fn failed_requests_count() {
+        let policy = HealthPolicy {
+            max_failures: 2,
+            cooldown: Duration::from_secs(60),
+            request_timeout: Duration::from_secs(30),
+        };
+        let mut breaker = Breaker::new();
+        let start = Instant::now();
+
+        // The request of the first search times out, its response is never parsed
+        assert!(breaker.admit(&policy, start).is_ok());
+        assert!(breaker
+            .admit(&policy, start + Duration::from_secs(10))
+            .is_ok());
+        assert_eq!(
+            breaker.expire(&policy, start + Duration::from_secs(20)),
+            None
+        );
+        assert_eq!(
+            breaker.expire(&policy, start + Duration::from_secs(30)),
+            None
+        );
+        assert_eq!(
+            breaker.health(&policy, start + Duration::from_secs(30)),
+            Health::Degraded(1)
+        );
+        assert_eq!(
+            breaker.record(true, &policy, start + Duration::from_secs(35)),
+            None,
+            "The second search completes"
+        );
+        assert_eq!(
+            breaker.expire(&policy, start + Duration::from_secs(60)),
+            None
+        );
+        assert_eq!(
+            breaker.health(&policy, start + Duration::from_secs(60)),
+            Health::Healthy
+        );
+
+        // Two more searches fail to connect
+        let later = start + Duration::from_secs(60);
+        assert!(breaker.admit(&policy, later).is_ok());
+        assert!(breaker.admit(&policy, later).is_ok());
+        assert_eq!(
+            breaker.expire(&policy, later + Duration::from_secs(30)),
+            Some(Event::Disabled(Duration::from_secs(60)))
+        );
+        let probe = later + Duration::from_secs(91);
+        assert!(breaker.admit(&policy, probe).is_ok());
+        assert!(breaker.admit(&policy, probe).is_err(), "A single probe");
+        assert_eq!(
+            breaker.expire(&policy, probe + Duration::from_secs(30)),
+            Some(Event::Disabled(Duration::from_secs(60))),
+            "The lost probe failed"
+        );
+        assert!(breaker
+            .admit(&policy, probe + Duration::from_secs(91))
+            .is_ok());
+    }
+}
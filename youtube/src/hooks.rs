@@ -0,0 +1,192 @@
+//! Hooks fired once a download completes.
+//!
+//! Downloads started with `progress::download` run every hook set with
+//! [`set_hooks`] after their `Finished` event, so files can be handed over to
+//! taggers, sync tools or notification services. Every hook receives the same
+//! JSON payload:
+//!
+//! ```json
+//! {
+//!   "file": "/music/Rick Astley - Never Gonna Give You Up.mp3",
+//!   "source": "youtube",
+//!   "url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+//!   "tags": { "title": "Never Gonna Give You Up", "artist": "Rick Astley", "album": null }
+//! }
+//! ```
+//!
+//! A failing hook doesn't fail the download, it's reported as
+//! `Event::Warning`. So is a hook still running after [`timeout`], which is
+//! killed.
+//!
+//! Downloads started with `download_fn` don't run the hooks: the host waits
+//! for their process, so the plugin never learns they completed.
+
+use crate::events;
+use serde_json::{json, Value};
+use std::{
+    error::Error,
+    io::Write,
+    process::{Command, Stdio},
+    sync::RwLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Action run once a download completes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hook {
+    /// Runs `program` with `args`, writing the payload to its stdin
+    Command { program: String, args: Vec<String> },
+    /// Posts the payload to the url
+    Webhook(String),
+}
+
+static HOOKS: RwLock<Vec<Hook>> = RwLock::new(Vec::new());
+static TIMEOUT: RwLock<Duration> = RwLock::new(Duration::from_secs(30));
+
+/// Runs `hooks` after the downloads completed from now on
+pub fn set_hooks(hooks: Vec<Hook>) {
+    *HOOKS.write().unwrap() = hooks;
+}
+
+/// Returns the hooks run after a download completes
+pub fn hooks() -> Vec<Hook> {
+    HOOKS.read().unwrap().clone()
+}
+
+/// Kills the hooks still running after `timeout`, 30 seconds by default
+pub fn set_timeout(timeout: Duration) {
+    *TIMEOUT.write().unwrap() = timeout;
+}
+
+/// Returns the time a hook can run
+pub fn timeout() -> Duration {
+    *TIMEOUT.read().unwrap()
+}
+
+/// Runs the hooks for the download described by `details`, the fields
+/// printed by `yt-dlp` once the file is in place
+pub(crate) fn completed(details: &Value) {
+    let hooks = hooks();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let payload = payload(details);
+    let timeout = timeout();
+    for hook in &hooks {
+        if let Err(err) = run(hook, &payload, timeout) {
+            events::warn(format!("Completion hook failed: {}", err));
+        }
+    }
+}
+
+fn payload(details: &Value) -> Value {
+    let field = |name: &str| details.get(name).filter(|value| !value.is_null());
+    json!({
+        "file": field("filepath"),
+        "source": "youtube",
+        "url": field("webpage_url"),
+        "tags": {
+            "title": field("title"),
+            // Music videos have an artist, other videos only an uploader
+            "artist": field("artist").or(field("uploader")),
+            "album": field("album"),
+        },
+    })
+}
+
+fn run(hook: &Hook, payload: &Value, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    match hook {
+        Hook::Command { program, args } => {
+            let mut child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(payload.to_string().as_bytes())?;
+            }
+            let deadline = Instant::now() + timeout;
+            let status = loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!(
+                        "{} killed after running for {}s",
+                        program,
+                        timeout.as_secs()
+                    )
+                    .into());
+                }
+                thread::sleep(Duration::from_millis(20));
+            };
+            if !status.success() {
+                return Err(format!("{} exited with {}", program, status).into());
+            }
+        }
+        Hook::Webhook(url) => {
+            reqwest::blocking::Client::builder()
+                .timeout(timeout)
+                .build()?
+                .post(url)
+                .header("content-type", "application/json")
+                .body(payload.to_string())
+                .send()?
+                .error_for_status()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{payload, run, Hook};
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+
+    #[cfg(unix)]
+    #[test]
+    fn command_hook() {
+        let details = json!({
+            "filepath": "/music/never.mp3",
+            "title": "Never Gonna Give You Up",
+            "artist": null,
+            "uploader": "Rick Astley",
+            "webpage_url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+        });
+        let payload = payload(&details);
+        assert_eq!(payload["tags"]["artist"], "Rick Astley");
+        assert_eq!(payload["tags"]["album"], serde_json::Value::Null);
+
+        let output = std::env::temp_dir().join(format!("phosphorus-hook-{}", std::process::id()));
+        let hook = Hook::Command {
+            program: "sh".into(),
+            args: vec!["-c".into(), format!("cat > '{}'", output.display())],
+        };
+        run(&hook, &payload, Duration::from_secs(10)).unwrap();
+        let received: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        let _ = std::fs::remove_file(&output);
+        assert_eq!(received, payload);
+
+        let failing = Hook::Command {
+            program: "sh".into(),
+            args: vec!["-c".into(), "exit 3".into()],
+        };
+        assert!(run(&failing, &payload, Duration::from_secs(10)).is_err());
+
+        let hanging = Hook::Command {
+            program: "sleep".into(),
+            args: vec!["10".into()],
+        };
+        let start = Instant::now();
+        assert!(run(&hanging, &payload, Duration::from_millis(200)).is_err());
+        assert!(start.elapsed() < Duration::from_secs(5), "Killed");
+    }
+}
@@ -0,0 +1,170 @@
+//! Coalescing of identical operations running at the same time.
+//!
+//! A double click, or a prefetch racing the playback, resolves the same
+//! stream twice in a row: the second resolution waits for the first one and
+//! shares its outcome, instead of spawning another process and counting
+//! towards YouTube's rate limits. Outcomes aren't cached once the operation
+//! returns, since the urls they hold expire.
+
+use std::{
+    error::Error,
+    sync::{Arc, Condvar, Mutex},
+};
+
+struct Call {
+    /// The error is shared as its message, errors can't be cloned
+    outcome: Mutex<Option<Result<String, String>>>,
+    done: Condvar,
+}
+
+static CALLS: Mutex<Vec<(String, Arc<Call>)>> = Mutex::new(Vec::new());
+
+/// Runs `operation`, unless an operation identified by `key` is running
+/// already: its outcome is returned once it's done.
+pub(crate) fn coalesce(
+    key: &str,
+    operation: impl FnOnce() -> Result<String, Box<dyn Error>>,
+) -> Result<String, Box<dyn Error>> {
+    let (call, leading) = {
+        let mut calls = CALLS.lock().unwrap();
+        match calls.iter().find(|(running, _)| running == key) {
+            Some((_, call)) => (call.clone(), false),
+            None => {
+                let call = Arc::new(Call {
+                    outcome: Mutex::new(None),
+                    done: Condvar::new(),
+                });
+                calls.push((key.to_string(), call.clone()));
+                (call, true)
+            }
+        }
+    };
+    if !leading {
+        let mut outcome = call.outcome.lock().unwrap();
+        while outcome.is_none() {
+            outcome = call.done.wait(outcome).unwrap();
+        }
+        return match outcome.as_ref() {
+            Some(Ok(value)) => Ok(value.clone()),
+            Some(Err(message)) => Err(message.clone().into()),
+            None => unreachable!(),
+        };
+    }
+
+    let mut leader = Leader {
+        key,
+        call,
+        finished: false,
+    };
+    let outcome = operation();
+    leader.finish(match &outcome {
+        Ok(value) => Ok(value.clone()),
+        Err(err) => Err(err.to_string()),
+    });
+
+    outcome
+}
+
+/// Running operation, finished even if it panics
+struct Leader<'a> {
+    key: &'a str,
+    call: Arc<Call>,
+    finished: bool,
+}
+
+impl Leader<'_> {
+    fn finish(&mut self, outcome: Result<String, String>) {
+        self.finished = true;
+        {
+            let mut calls = CALLS.lock().unwrap();
+            // A following call for the same key may be listed already
+            calls.retain(|(_, call)| !Arc::ptr_eq(call, &self.call));
+        }
+        *self.call.outcome.lock().unwrap() = Some(outcome);
+        self.call.done.notify_all();
+    }
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.finish(Err(format!("Coalesced operation `{}` panicked", self.key)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{coalesce, Call, Leader};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc, Condvar, Mutex,
+        },
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn identical_operations_run_once() {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let resolve = || {
+            coalesce("phosphorus-coalesce-test", || {
+                RUNS.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(200));
+                Ok("https://example.com/audio".into())
+            })
+            .map_err(|err| err.to_string())
+        };
+
+        let racing = thread::spawn(resolve);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(resolve().unwrap(), "https://example.com/audio");
+        assert_eq!(racing.join().unwrap().unwrap(), "https://example.com/audio");
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+
+        let failed = coalesce("phosphorus-coalesce-test", || Err("Refused".into()));
+        assert_eq!(
+            failed.unwrap_err().to_string(),
+            "Refused",
+            "Finished calls aren't kept"
+        );
+    }
+
+    #[test]
+    fn late_leader_spares_following_call() {
+        const KEY: &'static str = "phosphorus-coalesce-late-leader";
+        assert_eq!(coalesce(KEY, || Ok("first".into())).unwrap(), "first");
+
+        // Leader of the first call, dropped while a second call runs
+        let stale = Leader {
+            key: KEY,
+            call: Arc::new(Call {
+                outcome: Mutex::new(None),
+                done: Condvar::new(),
+            }),
+            finished: false,
+        };
+        let (started, on_start) = mpsc::channel();
+        let second = thread::spawn(move || {
+            coalesce(KEY, move || {
+                started.send(()).unwrap();
+                thread::sleep(Duration::from_millis(200));
+                Ok("second".into())
+            })
+            .map_err(|err| err.to_string())
+        });
+        on_start.recv().unwrap();
+        let waiter =
+            thread::spawn(|| coalesce(KEY, || Ok("third".into())).map_err(|err| err.to_string()));
+        thread::sleep(Duration::from_millis(50));
+        drop(stale);
+
+        assert_eq!(
+            waiter.join().unwrap().unwrap(),
+            "second",
+            "The waiter shares the outcome of the second call"
+        );
+        assert_eq!(second.join().unwrap().unwrap(), "second");
+    }
+}
@@ -0,0 +1,116 @@
+//! Helpers to talk to InnerTube, the JSON API behind the YouTube web client.
+
+use crate::{
+    locale,
+    trace::{self, Bundle},
+    USER_AGENT,
+};
+use serde_json::{json, Value};
+use std::error::Error;
+
+pub(crate) const SEARCH_URL: &'static str = "https://www.youtube.com/youtubei/v1/search";
+pub(crate) const BROWSE_URL: &'static str = "https://www.youtube.com/youtubei/v1/browse";
+pub(crate) const PLAYER_URL: &'static str = "https://www.youtube.com/youtubei/v1/player";
+const CLIENT_NAME: &'static str = "WEB";
+const CLIENT_VERSION: &'static str = "2.20220801.00.00";
+
+/// Builds the body of an InnerTube request, adding the client context to the
+/// `fields` object
+pub(crate) fn request_body(fields: Value) -> Value {
+    request_body_for(fields, locale::client_locale())
+}
+
+pub(crate) fn request_body_for(
+    mut fields: Value,
+    locale: (Option<String>, Option<String>),
+) -> Value {
+    let mut client = json!({
+        "clientName": CLIENT_NAME,
+        "clientVersion": CLIENT_VERSION,
+    });
+    let (language, region) = locale;
+    if let Some(language) = language {
+        client["hl"] = json!(language);
+    }
+    if let Some(region) = region {
+        client["gl"] = json!(region);
+    }
+    fields["context"] = json!({ "client": client });
+
+    fields
+}
+
+/// Adds the headers and the JSON `body` InnerTube requests need to `req_builder`
+pub(crate) fn prepare(
+    req_builder: reqwest::RequestBuilder,
+    body: &Value,
+) -> reqwest::RequestBuilder {
+    let req_builder = req_builder
+        .query(&[("prettyPrint", "false")])
+        .header("user-agent", USER_AGENT)
+        .header("content-type", "application/json");
+    let req_builder = match locale::accept_language() {
+        Some(languages) => req_builder.header("accept-language", languages),
+        None => req_builder,
+    };
+
+    req_builder.body(body.to_string())
+}
+
+/// Sends `body` to the InnerTube endpoint at `url` and returns the response,
+/// along with the trace bundle of the request
+pub(crate) async fn post(url: &str, body: &Value) -> Result<(Value, Bundle), Box<dyn Error>> {
+    let client = reqwest::Client::new();
+    let request = prepare(client.post(url), body).build()?;
+    let bundle = trace::request(&request);
+
+    let resp = client.execute(request).await?;
+    bundle.response(&resp);
+    let text = resp.text().await?;
+    bundle.body(&text);
+
+    Ok((serde_json::from_str(&text)?, bundle))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{prepare, request_body_for, SEARCH_URL};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn request_bodies() {
+        let expected: Value =
+            serde_json::from_str(include_str!("../tests/requests/2022-09-search.json")).unwrap();
+        let body = request_body_for(
+            json!({ "query": "rick astley" }),
+            (Some("it".into()), Some("IT".into())),
+        );
+        assert_eq!(body, expected);
+
+        let request = prepare(reqwest::Client::new().post(SEARCH_URL), &body)
+            .build()
+            .unwrap();
+        assert_eq!(request.url().query(), Some("prettyPrint=false"));
+        assert_eq!(request.headers()["content-type"], "application/json");
+        let sent: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(sent, expected);
+    }
+
+    #[test]
+    fn continuation_bodies() {
+        let page =
+            serde_json::from_str(include_str!("../tests/corpus/2022-09-search.json")).unwrap();
+        let token = crate::parse_results(&page, &crate::trace::Bundle::default())
+            .unwrap()
+            .continuation
+            .unwrap();
+        let expected: Value =
+            serde_json::from_str(include_str!("../tests/requests/2022-09-continuation.json"))
+                .unwrap();
+        assert_eq!(
+            request_body_for(json!({ "continuation": token }), (None, None)),
+            expected
+        );
+    }
+}
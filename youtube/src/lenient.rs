@@ -0,0 +1,157 @@
+//! Lenient parsing of search results.
+//!
+//! In strict mode, the default, a single malformed video fails the whole
+//! search. In lenient mode, videos missing non-essential fields are returned
+//! anyway and every missing field is recorded as a [`ParseWarning`]. Only the
+//! video id and title are essential; videos without them are skipped, with a
+//! warning as well. A summary of the warnings of a search is emitted as
+//! `Event::Warning`.
+//!
+//! `QueryResultData` has no optional fields, so missing values are replaced:
+//! thumbnails by the stock artwork of the video, the artist by an empty name
+//! and the duration by zero.
+
+use crate::{artwork, ARTIST_THUMBNAIL_SIZE, SOURCE_URL, TRACK_THUMBNAIL_SIZE};
+use phosphorus_core::plugin_manager::query::QueryResultData;
+use serde_json::Value;
+use std::{error::Error, sync::RwLock, time::Duration};
+
+/// How malformed results are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// A malformed result fails the whole search
+    Strict,
+    /// Malformed results are returned with placeholders, or skipped
+    Lenient,
+}
+
+/// Problem found while parsing a single result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWarning {
+    /// Id of the affected video, if it could be read
+    pub track_id: Option<String>,
+    pub message: String,
+}
+
+static MODE: RwLock<ParseMode> = RwLock::new(ParseMode::Strict);
+static LAST_WARNINGS: RwLock<Vec<ParseWarning>> = RwLock::new(Vec::new());
+
+/// Sets how malformed results are handled from now on
+pub fn set_mode(mode: ParseMode) {
+    *MODE.write().unwrap() = mode;
+}
+
+/// Returns how malformed results are handled
+pub fn mode() -> ParseMode {
+    *MODE.read().unwrap()
+}
+
+/// Returns the warnings produced by the last lenient parse
+pub fn last_warnings() -> Vec<ParseWarning> {
+    LAST_WARNINGS.read().unwrap().clone()
+}
+
+pub(crate) fn set_last_warnings(warnings: Vec<ParseWarning>) {
+    *LAST_WARNINGS.write().unwrap() = warnings;
+}
+
+/// Parses a `videoRenderer`, replacing missing non-essential fields. Returns
+/// `None` if an essential field is missing.
+pub(crate) fn parse_video(
+    source: &Value,
+    warnings: &mut Vec<ParseWarning>,
+) -> Option<QueryResultData> {
+    let track_id = match crate::read_track_id(source) {
+        Ok(track_id) if !track_id.is_empty() => track_id,
+        _ => {
+            warnings.push(ParseWarning {
+                track_id: None,
+                message: "Skipped result without id".into(),
+            });
+            return None;
+        }
+    };
+    let mut warn = |field: &str, err: Box<dyn Error>| {
+        warnings.push(ParseWarning {
+            track_id: Some(track_id.into()),
+            message: format!("Missing {}: {}", field, err),
+        });
+    };
+
+    let track_name = match crate::read_track_name(source) {
+        Ok(track_name) => track_name,
+        Err(err) => {
+            warn("title, result skipped", err);
+            return None;
+        }
+    };
+    let track_url = reqwest::Url::parse(&format!("{}?v={}", SOURCE_URL, track_id)).ok()?;
+
+    let stock_sizes = artwork::artwork_sizes(track_id);
+    let stock_artwork = |(width, height): (u32, u32)| {
+        artwork::best_for(&stock_sizes, width, height).map(|artwork| artwork.url.clone())
+    };
+    let track_thumbnail = match crate::read_track_thumbnail(source) {
+        Ok(url) => url,
+        Err(err) => {
+            warn("track thumbnail", err);
+            stock_artwork(TRACK_THUMBNAIL_SIZE)?
+        }
+    };
+    let artist_thumbnail = match crate::read_artist_thumbnail(source) {
+        Ok(url) => url,
+        Err(err) => {
+            warn("artist thumbnail", err);
+            stock_artwork(ARTIST_THUMBNAIL_SIZE)?
+        }
+    };
+    let artist_name = crate::read_artist_name(source).unwrap_or_else(|err| {
+        warn("artist", err);
+        ""
+    });
+    let duration = crate::read_duration(source).unwrap_or_else(|err| {
+        warn("duration", err);
+        Duration::ZERO
+    });
+
+    Some(QueryResultData::new(
+        track_id,
+        track_name,
+        track_url,
+        track_thumbnail,
+        artist_name,
+        artist_thumbnail,
+        duration,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_video;
+
+    #[test]
+    fn missing_fields() {
+        let live = serde_json::json!({
+            "videoId": "jfKfPfyJRdk",
+            "title": { "runs": [{ "text": "lofi hip hop radio" }] },
+            "longBylineText": { "runs": [{ "text": "Lofi Girl" }] },
+            "thumbnail": { "thumbnails": [] },
+        });
+        let mut warnings = vec![];
+        assert!(parse_video(&live, &mut warnings).is_some());
+        assert_eq!(
+            warnings.len(),
+            3,
+            "Track thumbnail, artist thumbnail and duration: {:?}",
+            warnings
+        );
+        assert!(warnings
+            .iter()
+            .all(|warning| warning.track_id.as_deref() == Some("jfKfPfyJRdk")));
+
+        let untitled = serde_json::json!({ "videoId": "jfKfPfyJRdk" });
+        let mut warnings = vec![];
+        assert!(parse_video(&untitled, &mut warnings).is_none());
+        assert_eq!(warnings.len(), 1);
+    }
+}
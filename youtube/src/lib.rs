@@ -1,4 +1,6 @@
 use async_trait::async_trait;
+use events::Event;
+use lenient::ParseMode;
 use phosphorus_core::plugin_manager::{
     downloader::*,
     error::{ParseError, PluginError},
@@ -6,40 +8,234 @@ use phosphorus_core::plugin_manager::{
     query::*,
     streamer::{StreamBuilder, Streamer},
 };
+use playlist::Playlist;
 use regex::Regex;
 use serde_json::Value;
 use std::{
     error::Error,
     process::{Child, Command, Stdio},
+    sync::Mutex,
     time::Duration,
 };
+use trace::Bundle;
+
+pub mod age;
+pub mod analysis;
+pub mod artwork;
+pub mod availability;
+pub mod binaries;
+pub mod chapters;
+#[cfg(feature = "color")]
+pub mod color;
+pub mod concurrency;
+#[cfg(test)]
+mod contract;
+pub mod download;
+pub mod events;
+#[cfg(all(test, unix))]
+mod fake;
+pub mod guardrails;
+pub mod health;
+pub mod hooks;
+mod inflight;
+mod innertube;
+pub mod lenient;
+#[cfg(test)]
+mod live;
+pub mod locale;
+pub mod network;
+pub mod playlist;
+pub mod progress;
+pub mod stats;
+pub mod trace;
+pub mod transcode;
+#[cfg(feature = "waveform")]
+pub mod waveform;
 
-const BASE_URL: &'static str = "https://youtube.com/results";
 const SOURCE_URL: &'static str = "https://youtube.com/watch";
+/// Host YouTube redirects to when cookies must be accepted first
+const CONSENT_HOST: &'static str = "consent.youtube.com";
 const USER_AGENT: &'static str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/104.0.5112.102 Safari/537.36";
 
+/// Most continuation pages fetched to resolve a single playlist, 100 entries
+/// each
+const MAX_PLAYLIST_PAGES: usize = 50;
+
+/// Display sizes the thumbnails attached to results are picked for
+const TRACK_THUMBNAIL_SIZE: (u32, u32) = (320, 180);
+const ARTIST_THUMBNAIL_SIZE: (u32, u32) = (68, 68);
+
 /// Plugin that allows to query YouTube and
 /// retrieve download-usefull information
-pub struct YouTube {}
+pub struct YouTube {
+    /// Token of the page following the last one parsed
+    continuation: Mutex<Option<String>>,
+    /// Playlists listed by the last page parsed
+    playlists: Mutex<Vec<Playlist>>,
+    /// Trace bundle of the last search built by `query`, for `parse`
+    bundle: Mutex<Bundle>,
+}
+
+/// A page of search results
+struct ResultsPage {
+    items: Vec<QueryResultData>,
+    /// Token of the following page
+    continuation: Option<String>,
+    playlists: Vec<Playlist>,
+}
+
+impl YouTube {
+    pub fn new() -> Self {
+        YouTube {
+            continuation: Mutex::new(None),
+            playlists: Mutex::new(vec![]),
+            bundle: Mutex::new(Bundle::default()),
+        }
+    }
+
+    /// Returns the playlists listed along with the last page of results. Use
+    /// their `url` as query to get their tracks.
+    pub fn playlists(&self) -> Vec<Playlist> {
+        self.playlists.lock().unwrap().clone()
+    }
+
+    /// Returns `true` if the last search has more results to fetch
+    pub fn has_next_page(&self) -> bool {
+        self.continuation.lock().unwrap().is_some()
+    }
+
+    /// Fetches the page of results following the last one parsed, for the
+    /// same query. Returns `None` once every page has been fetched.
+    pub async fn next_page(&self) -> Result<Option<QueryResult>, Box<dyn Error>> {
+        let token = match self.continuation.lock().unwrap().clone() {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+
+        health::admit()?;
+        let result = self.fetch_page(&token).await;
+        health::record(&result);
+
+        Ok(Some(result?))
+    }
+
+    /// Fetches the page of results following `token`
+    async fn fetch_page(&self, token: &str) -> Result<QueryResult, Box<dyn Error>> {
+        let body = innertube::request_body(serde_json::json!({ "continuation": token }));
+        let (json, bundle) = innertube::post(innertube::SEARCH_URL, &body).await?;
+        let page = parse_results(&json, &bundle)?;
+
+        Ok(QueryResult::new(self.keep_page(page)))
+    }
+
+    /// Parses the response to a search or to a playlist
+    async fn parse_response(
+        &self,
+        resp: reqwest::Response,
+        bundle: &Bundle,
+    ) -> Result<QueryResult, Box<dyn Error>> {
+        bundle.response(&resp);
+        if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || resp.url().host_str() == Some(CONSENT_HOST)
+        {
+            let reason = format!("Search refused with {} at {}", resp.status(), resp.url());
+            bundle.decision(&reason);
+            events::emit(Event::Blocked(reason));
+            return Err(Box::new(ParseError::InvalidResponseText));
+        }
+        let text = resp.text().await?;
+        bundle.body(&text);
+
+        let json: Value = match serde_json::from_str(&text) {
+            Ok(json) => json,
+            Err(cause) => return Err(Box::new(ParseError::JsonUnparsable(cause.to_string()))),
+        };
+        if playlist::is_browse_response(&json) {
+            let items = self.resolve_playlist(&json, bundle).await?;
+            // The whole playlist is returned at once
+            *self.continuation.lock().unwrap() = None;
+            self.playlists.lock().unwrap().clear();
+            return Ok(QueryResult::new(items));
+        }
+        let page = parse_results(&json, bundle)?;
+
+        Ok(QueryResult::new(self.keep_page(page)))
+    }
+
+    /// Keeps the continuation and the playlists of `page`, returning its items
+    fn keep_page(&self, page: ResultsPage) -> Vec<QueryResultData> {
+        *self.continuation.lock().unwrap() = page.continuation;
+        *self.playlists.lock().unwrap() = page.playlists;
+        page.items
+    }
+
+    /// Parses the first page of a playlist and fetches the following ones
+    async fn resolve_playlist(
+        &self,
+        json: &Value,
+        bundle: &Bundle,
+    ) -> Result<Vec<QueryResultData>, Box<dyn Error>> {
+        let (mut items, mut continuation) = playlist::parse_page(json, bundle)?;
+        let mut pages = 1;
+        while let Some(token) = continuation {
+            if pages == MAX_PLAYLIST_PAGES {
+                events::warn_traced(
+                    bundle,
+                    format!(
+                        "Playlist truncated to its first {} tracks, after {} pages",
+                        items.len(),
+                        pages
+                    ),
+                );
+                break;
+            }
+            let body = innertube::request_body(serde_json::json!({ "continuation": token }));
+            let (json, page_bundle) = innertube::post(innertube::BROWSE_URL, &body).await?;
+            let (page_items, next) = playlist::parse_page(&json, &page_bundle)?;
+            items.extend(page_items);
+            continuation = next;
+            pages += 1;
+        }
+
+        Ok(items)
+    }
+}
+
+impl Default for YouTube {
+    fn default() -> Self {
+        YouTube::new()
+    }
+}
 
 #[async_trait]
 impl Plugin for YouTube {
     fn method(&self) -> reqwest::Method {
-        reqwest::Method::GET
+        reqwest::Method::POST
     }
 
     fn base_url(&self) -> &'static str {
-        BASE_URL
+        innertube::SEARCH_URL
     }
 
     fn query(
         &self,
         info: &QueryInfo,
-        mut req_builder: reqwest::RequestBuilder,
+        req_builder: reqwest::RequestBuilder,
     ) -> Result<reqwest::Request, Box<dyn Error>> {
-        req_builder = req_builder.query(&[("search_query", info.raw())]);
-        req_builder = req_builder.header("user-agent", USER_AGENT);
-        Ok(req_builder.build()?)
+        health::admit()?;
+        let browse_id = playlist::browse_id(info.raw());
+        let body = match &browse_id {
+            Some(browse_id) => serde_json::json!({ "browseId": browse_id }),
+            None => serde_json::json!({ "query": info.raw() }),
+        };
+        let body = innertube::request_body(body);
+        let mut request = innertube::prepare(req_builder, &body).build()?;
+        if browse_id.is_some() {
+            let browse_url = reqwest::Url::parse(innertube::BROWSE_URL)?;
+            request.url_mut().set_path(browse_url.path());
+        }
+        *self.bundle.lock().unwrap() = trace::request(&request);
+        Ok(request)
     }
 
     async fn parse(
@@ -47,20 +243,10 @@ impl Plugin for YouTube {
         _info: &QueryInfo,
         resp: reqwest::Response,
     ) -> Result<QueryResult, Box<dyn Error>> {
-        let text = resp.text().await?;
-        let json = get_json(&text)?;
-
-        let contents = json.get("contents").unwrap().as_array().unwrap();
-        let mut items = vec![];
-        for token in contents {
-            let raw = token.read("videoRenderer");
-            if raw.is_ok() {
-                let item = QueryResultData::parse(raw.unwrap())?;
-                items.push(item);
-            }
-        }
-
-        Ok(QueryResult::new(items))
+        let bundle = std::mem::take(&mut *self.bundle.lock().unwrap());
+        let result = self.parse_response(resp, &bundle).await;
+        health::record(&result);
+        result
     }
 
     fn download(&self) -> Downloader {
@@ -74,175 +260,342 @@ impl Plugin for YouTube {
 
 /// Creates a sub-process that downloads the media associated to `url`. That media is than
 /// saved as `file_name.[ext]`. The handler to the sub-process is returned.
+/// The download is refused if it doesn't respect the `guardrails` or the
+/// `network` policy. Urls returned by `download::clip_url` only download the
+/// requested time range. See `progress::download` for a download reporting
+/// its progress and running the `hooks` once it completes.
 pub fn download_fn(url: &str, file_name: &str) -> Result<Child, Box<dyn Error>> {
-    let mut download_command = Command::new("yt-dlp");
-    download_command.args(["--extract-audio", "--audio-format", "mp3"]);
-    let output_file = format!("{}.%(ext)s", file_name);
-    download_command.args(["-o", &output_file]);
-    download_command.arg(url);
-
+    let mut download_command = download_command(url, file_name)?;
+    let permit = concurrency::acquire()?;
     let handler = download_command
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
-        .spawn()?;
+        .spawn()
+        .map_err(|err| events::spawn_error("yt-dlp", err))?;
+    permit.hand_over(handler.id());
+    guardrails::count();
 
     Ok(handler)
 }
 
+/// Builds the command downloading the media at `url`, see `download_fn`
+fn download_command(url: &str, file_name: &str) -> Result<Command, Box<dyn Error>> {
+    network::check_download()?;
+    let limits = guardrails::admit()?;
+
+    let options = download::options();
+    if options.album_mode && options.format != download::AudioFormat::M4a {
+        events::warn(format!(
+            "Album mode downloads m4a instead of {}",
+            options.format.name()
+        ));
+    }
+    let mut download_command = binaries::yt_dlp();
+    download_command.args(download::extractor_args(&options));
+    download_command.args(&limits);
+    download_command.args(age::extractor_args());
+    let (url, clip) = download::clip_args(url);
+    download_command.args(&clip);
+    download_command.args(download::output_args(&options, file_name));
+    download_command.arg(url);
+
+    Ok(download_command)
+}
+
 pub fn stream_fn(url: &str, file_name: &str) -> Result<std::process::Child, Box<dyn Error>> {
-    let mut url_getter = Command::new("youtube-dl");
+    let mut stream_command = stream_command(url, file_name)?;
+    let permit = concurrency::acquire()?;
+    let handler = stream_command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| events::spawn_error("ffmpeg", err))?;
+    permit.hand_over(handler.id());
+
+    Ok(handler)
+}
+
+/// Builds the command streaming the media at `url`, see `stream_fn`
+fn stream_command(url: &str, file_name: &str) -> Result<Command, Box<dyn Error>> {
+    let url = resolve_audio_url(url)?;
+
+    Ok(transcode::command(&url, file_name)?)
+}
+
+/// Returns the url of the audio stream of the video at `url`. Resolutions of
+/// the same stream running at the same time share one process.
+fn resolve_audio_url(url: &str) -> Result<String, Box<dyn Error>> {
+    let bitrate = network::stream_bitrate();
+    let key = format!("audio-url {} {:?} {:?}", url, bitrate, age::policy());
+    inflight::coalesce(&key, || spawn_audio_url(url, bitrate))
+}
+
+fn spawn_audio_url(url: &str, bitrate: Option<u32>) -> Result<String, Box<dyn Error>> {
+    let mut url_getter = binaries::youtube_dl();
     url_getter.arg("-g");
+    url_getter.args(age::extractor_args());
+    if let Some(bitrate) = bitrate {
+        url_getter.args(["-f", &format!("bestaudio[abr<={}]/worstaudio", bitrate)]);
+    }
     url_getter.arg(url);
-    let urls = url_getter.output()?;
-    let urls = std::str::from_utf8(&urls.stdout).unwrap();
+    let _permit = concurrency::acquire()?;
+    let output = url_getter
+        .output()
+        .map_err(|err| events::spawn_error("youtube-dl", err))?;
+    let urls = std::str::from_utf8(&output.stdout).unwrap();
 
     let regex = Regex::new(r#"(https://.*)\s*$"#)?;
     let matches = regex.captures(urls);
 
-    let url = if let Some(matches) = matches {
+    if let Some(matches) = matches {
         if let Some(text) = matches.get(0) {
-            text.as_str().trim()
+            Ok(text.as_str().trim().to_string())
         } else {
-            return Err(Box::new(PluginError::StreamError(
+            Err(Box::new(PluginError::StreamError(
                 "No download url found for the audio stream".into(),
-            )));
+            )))
         }
     } else {
-        return Err(Box::new(PluginError::StreamError(
-            "No download url found".into(),
-        )));
-    };
-
-    let mut stream_getter = Command::new("ffmpeg");
-    stream_getter.args(["-i", url]);
-    stream_getter.args(["-c:a", "libmp3lame"]);
-    let file = format!("file:{}", file_name);
-    stream_getter.arg(&file);
-    stream_getter.arg("-y"); // If `file_name` exists, it's overwritten
-
-    let handler = stream_getter
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    Ok(handler)
+        let errors = String::from_utf8_lossy(&output.stderr);
+        // `-g` implies `--quiet`, so a skipped video usually only shows as a
+        // success without any url
+        let reason = match urls.lines().chain(errors.lines()).find_map(age::refusal) {
+            Some(reason) => reason,
+            None if output.status.success() => {
+                "youtube-dl skipped the video, it doesn't respect the age policy".into()
+            }
+            None => progress::failure_reason(&errors).unwrap_or(output.status.to_string()),
+        };
+        Err(Box::new(PluginError::StreamError(reason)))
+    }
 }
 
-/// Given a json string, returns the associated `serde_json::Value`
-/// instance.
-fn get_json(text: &str) -> Result<Value, Box<dyn Error>> {
-    let regex = Regex::new(r#"itemSectionRenderer":(\{"contents")"#).unwrap();
-    let begin_range = match regex.captures(&text) {
-        Some(groups) => match groups.get(1) {
-            Some(group) => group.range(),
-            None => {
-                return Err(Box::new(ParseError::ParsableTextNotFound));
+/// Parses the videos and the playlists listed in a search or continuation
+/// response, along with the token of the following page, if any
+fn parse_results(json: &Value, bundle: &Bundle) -> Result<ResultsPage, Box<dyn Error>> {
+    if let Some(error) = json.get("error") {
+        bundle.decision(&format!("Search failed: {}", error));
+        return Err(Box::new(ParseError::InvalidResponseText));
+    }
+
+    let mut contents = vec![];
+    let mut continuation = None;
+    for section in read_sections(json)? {
+        if let Some(items) = section
+            .get("itemSectionRenderer")
+            .and_then(|renderer| renderer.get("contents"))
+            .and_then(Value::as_array)
+        {
+            for item in items {
+                // Shelves ("From the artist", "Latest from") list videos of their own
+                match item
+                    .pointer("/shelfRenderer/content/verticalListRenderer/items")
+                    .and_then(Value::as_array)
+                {
+                    Some(shelved) => contents.extend(shelved),
+                    None => contents.push(item),
+                }
             }
-        },
-        None => {
-            return Err(Box::new(ParseError::ParsableTextNotFound));
+        } else if let Some(renderer) = section.get("continuationItemRenderer") {
+            continuation = renderer
+                .read("continuationEndpoint")?
+                .read("continuationCommand")?
+                .read_str("token")
+                .ok()
+                .map(String::from);
+        } else {
+            bundle.decision(&format!("Skipped section {}", item_kind(section)));
         }
-    };
+    }
 
-    let regex =
-        Regex::new(r#"\],"trackingParams":"[a-zA-Z0-9=_-]*"(\})\},\{"continuationItemRenderer""#)
-            .unwrap();
-    let end_range = match regex.captures(&text) {
-        Some(groups) => match groups.get(1) {
-            Some(group) => group.range(),
-            None => {
-                return Err(Box::new(ParseError::ParsableTextNotFound));
+    let mode = lenient::mode();
+    let mut items = vec![];
+    let mut warnings = vec![];
+    let mut skipped = 0;
+    let mut playlists = vec![];
+    for token in contents {
+        if let Some(raw) = token.get("playlistRenderer") {
+            match playlist::read_playlist(raw) {
+                Ok(playlist) => playlists.push(playlist),
+                Err(err) => bundle.decision(&format!("Invalid playlistRenderer: {}", err)),
             }
-        },
-        None => {
-            return Err(Box::new(ParseError::ParsableTextNotFound));
+            continue;
         }
-    };
+        let raw = match token.read("videoRenderer") {
+            Ok(raw) => raw,
+            Err(_) => {
+                bundle.decision(&format!("Skipped item {}", item_kind(token)));
+                continue;
+            }
+        };
+
+        match mode {
+            ParseMode::Strict => {
+                let item = QueryResultData::parse(raw).map_err(|err| {
+                    bundle.decision(&format!("Invalid videoRenderer: {}", err));
+                    err
+                })?;
+                items.push(item);
+            }
+            ParseMode::Lenient => match lenient::parse_video(raw, &mut warnings) {
+                Some(item) => items.push(item),
+                None => skipped += 1,
+            },
+        }
+    }
+    for warning in &warnings {
+        bundle.decision(&format!("Lenient parse: {}", warning.message));
+    }
+    if !warnings.is_empty() {
+        // Every skipped result has a single warning
+        events::warn_traced(
+            bundle,
+            format!(
+                "{} results skipped, {} missing fields replaced by placeholders",
+                skipped,
+                warnings.len() - skipped
+            ),
+        );
+    }
+    bundle.decision(&format!("Parsed {} items", items.len()));
+    if mode == ParseMode::Lenient {
+        lenient::set_last_warnings(warnings);
+    }
 
-    if begin_range.start >= end_range.end {
-        return Err(Box::new(ParseError::InvalidResponseText));
+    if continuation.is_none() {
+        bundle.decision("Last page of results");
     }
 
-    match serde_json::from_str(&text[begin_range.start..end_range.end]) {
-        Ok(value) => Ok(value),
-        Err(cause) => Err(Box::new(ParseError::JsonUnparsable(cause.to_string()))),
+    Ok(ResultsPage {
+        items,
+        continuation,
+        playlists,
+    })
+}
+
+/// Returns the sections of a search response, or of a continuation response
+fn read_sections(json: &Value) -> Result<&Vec<Value>, Box<dyn Error>> {
+    let sections = match json.get("onResponseReceivedCommands") {
+        Some(commands) => commands
+            .read_first()?
+            .read("appendContinuationItemsAction")?
+            .read("continuationItems")?,
+        None => json
+            .read("contents")?
+            .read("twoColumnSearchResultsRenderer")?
+            .read("primaryContents")?
+            .read("sectionListRenderer")?
+            .read("contents")?,
+    };
+
+    match sections.as_array() {
+        Some(sections) => Ok(sections),
+        None => Err(Box::new(ParseError::InvalidResponseText)),
     }
 }
 
 impl Deserializable<Value, QueryResultData, YouTube> for QueryResultData {
     fn parse(source: &Value) -> Result<Self, Box<dyn Error>> {
-        let artist_thumbnail = source
+        let track_id = read_track_id(source)?;
+        let track_url = reqwest::Url::parse(&format!("{}?v={}", SOURCE_URL, track_id)).unwrap();
+
+        Ok(QueryResultData::new(
+            track_id,
+            read_track_name(source)?,
+            track_url,
+            read_track_thumbnail(source)?,
+            read_artist_name(source)?,
+            read_artist_thumbnail(source)?,
+            read_duration(source)?,
+        ))
+    }
+}
+
+fn read_track_id(source: &Value) -> Result<&str, Box<dyn Error>> {
+    source.read_str("videoId")
+}
+
+fn read_track_name(source: &Value) -> Result<&str, Box<dyn Error>> {
+    source
+        .read("title")?
+        .read("runs")?
+        .read_first()?
+        .read_str("text")
+}
+
+fn read_track_thumbnail(source: &Value) -> Result<reqwest::Url, Box<dyn Error>> {
+    let track_thumbnails = artwork::read_sizes(source.read("thumbnail")?.read("thumbnails")?)?;
+    let (width, height) = TRACK_THUMBNAIL_SIZE;
+    let track_thumbnail = artwork::best_for(&track_thumbnails, width, height)
+        .ok_or_else(|| ParseError::JsonUnparsable("No track thumbnail".into()))?;
+
+    Ok(track_thumbnail.url.clone())
+}
+
+fn read_artist_name(source: &Value) -> Result<&str, Box<dyn Error>> {
+    read_artist_name_from(source, "longBylineText")
+}
+
+/// Reads the artist name out of the byline `field`
+fn read_artist_name_from<'a>(source: &'a Value, field: &str) -> Result<&'a str, Box<dyn Error>> {
+    source
+        .read(field)?
+        .read("runs")?
+        .read_first()?
+        .read_str("text")
+}
+
+fn read_artist_thumbnail(source: &Value) -> Result<reqwest::Url, Box<dyn Error>> {
+    let artist_thumbnails = artwork::read_sizes(
+        source
             .read("channelThumbnailSupportedRenderers")?
             .read("channelThumbnailWithLinkRenderer")?
             .read("thumbnail")?
-            .read("thumbnails")?
-            .as_array()
-            .unwrap()[0]
-            .read("url")?
-            .as_str()
-            .unwrap();
-        let artist_thumbnail = reqwest::Url::parse(artist_thumbnail)?;
-        let artist_name = source
-            .read("longBylineText")?
-            .read("runs")?
-            .as_array()
-            .unwrap()[0]
-            .read("text")?
-            .as_str()
-            .unwrap();
+            .read("thumbnails")?,
+    )?;
+    let (width, height) = ARTIST_THUMBNAIL_SIZE;
+    let artist_thumbnail = artwork::best_for(&artist_thumbnails, width, height)
+        .ok_or_else(|| ParseError::JsonUnparsable("No artist thumbnail".into()))?;
 
-        // Reads duration an converts it to a `Duration` instance
-        let duration = source
-            .read("lengthText")?
-            .read("simpleText")?
-            .as_str()
-            .unwrap();
-        let duration_tokens: Vec<u64> = duration
-            .split(":")
-            .map(|token| u64::from_str_radix(token, 10).unwrap())
-            .collect();
-
-        let mut i = duration_tokens.len();
-        let mut total_duration = 0;
-        for token in duration_tokens {
-            i -= 1;
-            total_duration += token * 60_u64.pow(i as u32);
-        }
-
-        let duration = Duration::from_secs(total_duration);
+    Ok(artist_thumbnail.url.clone())
+}
 
-        let track_id = source.read("videoId")?.as_str().unwrap();
-        let track_url = reqwest::Url::parse(&format!("{}?v={}", SOURCE_URL, track_id)).unwrap();
+/// Reads duration an converts it to a `Duration` instance
+fn read_duration(source: &Value) -> Result<Duration, Box<dyn Error>> {
+    let duration = source.read("lengthText")?.read_str("simpleText")?;
+    let duration_tokens = duration
+        .split(':')
+        .map(|token| token.parse::<u64>())
+        .collect::<Result<Vec<u64>, _>>()
+        .map_err(|_| ParseError::JsonUnparsable(format!("Invalid duration `{}`", duration)))?;
+
+    let mut i = duration_tokens.len();
+    let mut total_duration = 0;
+    for token in duration_tokens {
+        i -= 1;
+        total_duration += token * 60_u64.pow(i as u32);
+    }
 
-        let track_name = source.read("title")?.read("runs")?.as_array().unwrap()[0]
-            .read("text")?
-            .as_str()
-            .unwrap();
-        let track_thumbnail = source
-            .read("thumbnail")?
-            .read("thumbnails")?
-            .as_array()
-            .unwrap()[0]
-            .read("url")?
-            .as_str()
-            .unwrap();
-        let track_thumbnail = reqwest::Url::parse(track_thumbnail)?;
+    Ok(Duration::from_secs(total_duration))
+}
 
-        Ok(QueryResultData::new(
-            track_id,
-            track_name,
-            track_url,
-            track_thumbnail,
-            artist_name,
-            artist_thumbnail,
-            duration,
-        ))
+/// Name of the renderer wrapped by an item of the results list
+fn item_kind(token: &Value) -> String {
+    match token.as_object().and_then(|item| item.keys().next()) {
+        Some(key) => key.clone(),
+        None => String::from("<empty>"),
     }
 }
 
 trait YTItemParser {
     /// Utility to retrieve a `Value` from a map representing a JSON
     fn read(&self, field_name: &str) -> Result<&Value, Box<dyn Error>>;
+
+    /// Utility to retrieve a string from a map representing a JSON
+    fn read_str(&self, field_name: &str) -> Result<&str, Box<dyn Error>>;
+
+    /// Utility to retrieve the first element of an array
+    fn read_first(&self) -> Result<&Value, Box<dyn Error>>;
 }
 
 impl YTItemParser for Value {
@@ -257,29 +610,146 @@ impl YTItemParser for Value {
             }
         }
     }
+
+    fn read_str(&self, field_name: &str) -> Result<&str, Box<dyn Error>> {
+        match self.read(field_name)?.as_str() {
+            Some(text) => Ok(text),
+            None => Err(Box::new(ParseError::JsonUnparsable(format!(
+                "Field `{}` isn't a string",
+                field_name
+            )))),
+        }
+    }
+
+    fn read_first(&self) -> Result<&Value, Box<dyn Error>> {
+        match self.as_array().and_then(|array| array.first()) {
+            Some(value) => Ok(value),
+            None => Err(Box::new(ParseError::JsonUnparsable(
+                "Missing array element".into(),
+            ))),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use regex::Regex;
+    /// Captured InnerTube response, named `<yyyy-mm>-<variant>` after the date
+    /// the layout was first seen
+    struct Case {
+        name: &'static str,
+        text: &'static str,
+        /// Number of videos listed and whether a following page is linked,
+        /// `None` for responses that can't be parsed
+        expected: Option<(usize, bool)>,
+    }
+
+    /// The consent wall is the HTML page searches are redirected to
+    const CORPUS: [Case; 7] = [
+        Case {
+            name: "2022-09-search",
+            text: include_str!("../tests/corpus/2022-09-search.json"),
+            expected: Some((3, true)),
+        },
+        Case {
+            name: "2022-09-music-shelf",
+            text: include_str!("../tests/corpus/2022-09-music-shelf.json"),
+            expected: Some((4, true)),
+        },
+        Case {
+            name: "2022-09-shorts-shelf",
+            text: include_str!("../tests/corpus/2022-09-shorts-shelf.json"),
+            expected: Some((2, true)),
+        },
+        Case {
+            name: "2022-10-no-continuation",
+            text: include_str!("../tests/corpus/2022-10-no-continuation.json"),
+            expected: Some((2, false)),
+        },
+        Case {
+            name: "2022-10-continuation",
+            text: include_str!("../tests/corpus/2022-10-continuation.json"),
+            expected: Some((1, true)),
+        },
+        Case {
+            name: "2022-10-error",
+            text: include_str!("../tests/corpus/2022-10-error.json"),
+            expected: None,
+        },
+        Case {
+            name: "2022-10-consent",
+            text: include_str!("../tests/corpus/2022-10-consent.html"),
+            expected: None,
+        },
+    ];
 
-    /// This function checks is the regex used to retrieve the starting point
-    /// for data in raw response is still valid.
     #[test]
-    fn regex_extraction() {
-        let regex = Regex::new(r#"itemSectionRenderer[\S|\s]*(\{\s*"contents)"#).unwrap();
-        let text = r#"ntents":[{"itemSectionRenderer":{"contents":[{"#;
-        let group = regex.captures(&text).unwrap().get(1).unwrap();
+    fn response_corpus() {
+        for Case {
+            name,
+            text,
+            expected,
+        } in CORPUS
+        {
+            let parsed = serde_json::from_str(text)
+                .map_err(Box::from)
+                .and_then(|json| super::parse_results(&json, &super::Bundle::default()))
+                .map(|page| (page.items.len(), page.continuation.is_some()));
+            match expected {
+                Some(expected) => assert_eq!(
+                    parsed.ok(),
+                    Some(expected),
+                    "Testing corpus response `{}`",
+                    name
+                ),
+                None => assert!(parsed.is_err(), "Testing corpus response `{}`", name),
+            }
+        }
 
+        let json = serde_json::from_str(CORPUS[3].text).unwrap();
+        let playlists = super::parse_results(&json, &super::Bundle::default())
+            .unwrap()
+            .playlists;
+        assert_eq!(playlists.len(), 1);
+        assert_eq!(playlists[0].track_count, Some(42));
         assert_eq!(
-            group.as_str(),
-            r#"{"contents"#,
-            "Testing for the content of the matched group"
+            playlists[0].url(),
+            "https://youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stream_pipeline() {
+        use crate::fake::{self, Script};
+
+        let fakes = fake::install();
+        fakes.youtube_dl(
+            Script::new()
+                .stdout("https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag=18")
+                .stdout("https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag=251"),
         );
+        let status = super::stream_fn("https://youtube.com/watch?v=dQw4w9WgXcQ", "out.mp3")
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(status.success());
+        let args = fakes.args("ffmpeg");
+        let input = args.iter().position(|arg| arg == "-i").unwrap();
         assert_eq!(
-            (group.start(), group.end()),
-            (32, 42),
-            "Testing for the range of the matched group"
+            args[input + 1],
+            "https://rr1---sn-a5mekn6s.googlevideo.com/videoplayback?itag=251",
+            "Streams the last url, the audio one"
         );
+        assert_eq!(args.last().unwrap(), "-y");
+
+        fakes.ffmpeg(Script::new().exit(1));
+        let status = super::stream_fn("https://youtube.com/watch?v=dQw4w9WgXcQ", "out.mp3")
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(!status.success());
+
+        fakes.youtube_dl(Script::new().stderr("ERROR: Video unavailable").exit(1));
+        assert!(super::stream_fn("https://youtube.com/watch?v=dQw4w9WgXcQ", "out.mp3").is_err());
     }
 }
@@ -0,0 +1,116 @@
+//! Checks against live YouTube, ignored by default.
+//!
+//! They run a real search, a small download, a short stream and a download
+//! refused by the guardrails, with the binaries installed on the machine, and
+//! print a scorecard telling which step is broken upstream. Run them with
+//! `cargo test live -- --ignored --nocapture`.
+
+use crate::{
+    download_fn, guardrails, innertube, parse_results,
+    progress::{self, Progress},
+    stream_fn, transcode,
+};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// "Me at the zoo", the first video uploaded, 19 seconds long
+const TRACK_ID: &'static str = "jNQXAC9IVRw";
+const QUERY: &'static str = "me at the zoo jawed";
+
+fn workspace() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("phosphorus-live-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn search() -> Result<(), Box<dyn Error>> {
+    let body = innertube::request_body(serde_json::json!({ "query": QUERY }));
+    let (json, bundle) = innertube::post(innertube::SEARCH_URL, &body).await?;
+    let page = parse_results(&json, &bundle)?;
+    if page.items.is_empty() {
+        return Err("No result".into());
+    }
+
+    Ok(())
+}
+
+fn download(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let file_name = dir.join("download");
+    let url = format!("{}?v={}", crate::SOURCE_URL, TRACK_ID);
+    let status = download_fn(&url, &file_name.to_string_lossy())?.wait()?;
+    if !status.success() {
+        return Err(format!("yt-dlp exited with {}", status).into());
+    }
+    if !dir.join("download.mp3").exists() {
+        return Err("No file downloaded".into());
+    }
+
+    Ok(())
+}
+
+fn stream(dir: &Path) -> Result<(), Box<dyn Error>> {
+    transcode::set_options(transcode::TranscodeOptions {
+        stop_after: Some(Duration::from_secs(3)),
+        ..Default::default()
+    })?;
+    let file_name = dir.join("stream.mp3");
+    let url = format!("{}?v={}", crate::SOURCE_URL, TRACK_ID);
+    let status = stream_fn(&url, &file_name.to_string_lossy())?.wait();
+    transcode::set_options(Default::default())?;
+    let status = status?;
+    if !status.success() {
+        return Err(format!("ffmpeg exited with {}", status).into());
+    }
+    if fs::metadata(&file_name)?.len() == 0 {
+        return Err("Empty stream".into());
+    }
+
+    Ok(())
+}
+
+/// The real yt-dlp is quiet with `--print`, the refusal has to be noticed
+/// anyway
+fn refusal(dir: &Path) -> Result<(), Box<dyn Error>> {
+    guardrails::set(guardrails::Guardrails {
+        max_duration: Some(Duration::from_secs(5)),
+        ..guardrails::Guardrails::new()
+    });
+    let file_name = dir.join("refusal");
+    let url = format!("{}?v={}", crate::SOURCE_URL, TRACK_ID);
+    let outcome = progress::download(&url, &file_name.to_string_lossy())
+        .map(|transfer| transfer.events.iter().last());
+    guardrails::set(guardrails::Guardrails::new());
+    match outcome? {
+        Some(Progress::Failed(_)) => Ok(()),
+        outcome => Err(format!("Refused download ended with {:?}", outcome).into()),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs network access, yt-dlp, youtube-dl and ffmpeg"]
+async fn scorecard() {
+    let dir = workspace();
+    let checks = [
+        ("search", search().await),
+        ("download", download(&dir)),
+        ("stream", stream(&dir)),
+        ("refusal", refusal(&dir)),
+    ];
+    let _ = fs::remove_dir_all(&dir);
+
+    println!("\nyoutube scorecard");
+    for (name, result) in &checks {
+        match result {
+            Ok(()) => println!("  {:<10} ok", name),
+            Err(err) => println!("  {:<10} BROKEN: {}", name, err),
+        }
+    }
+    assert!(
+        checks.iter().all(|(_, result)| result.is_ok()),
+        "Some checks are broken"
+    );
+}
@@ -0,0 +1,90 @@
+//! Languages the host prefers metadata in.
+//!
+//! YouTube localizes titles, artist names and descriptions when the uploader
+//! provided translations. The languages set with [`set_languages`], in order
+//! of preference, are sent with every InnerTube request: the first one as the
+//! interface language, all of them as `Accept-Language`. The region of the
+//! first language carrying one selects the market results come from.
+//! Results don't tell which language they're in, so they aren't tagged.
+
+use std::sync::RwLock;
+
+static LANGUAGES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Prefers metadata in `languages`, BCP 47 tags such as `it` or `pt-BR`,
+/// from the most preferred one. An empty list keeps YouTube's defaults.
+pub fn set_languages(languages: Vec<String>) {
+    *LANGUAGES.write().unwrap() = languages
+        .into_iter()
+        .map(|language| language.trim().replace('_', "-"))
+        .filter(|language| !language.is_empty())
+        .collect();
+}
+
+/// Returns the preferred languages, from the most preferred one
+pub fn languages() -> Vec<String> {
+    LANGUAGES.read().unwrap().clone()
+}
+
+/// Interface language and region of the InnerTube client
+pub(crate) fn client_locale() -> (Option<String>, Option<String>) {
+    locale_of(&LANGUAGES.read().unwrap())
+}
+
+/// Value of the `Accept-Language` header, weighting languages by preference
+pub(crate) fn accept_language() -> Option<String> {
+    accept_language_of(&LANGUAGES.read().unwrap())
+}
+
+fn locale_of(languages: &[String]) -> (Option<String>, Option<String>) {
+    let language = languages.first().cloned();
+    let region = languages.iter().find_map(|language| {
+        language
+            .split('-')
+            .skip(1)
+            .find(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_alphabetic()))
+            .map(str::to_uppercase)
+    });
+
+    (language, region)
+}
+
+fn accept_language_of(languages: &[String]) -> Option<String> {
+    if languages.is_empty() {
+        return None;
+    }
+
+    let weighted: Vec<String> = languages
+        .iter()
+        .enumerate()
+        .map(|(index, language)| match index {
+            0 => language.clone(),
+            // Weights go down to 0.1 at most
+            _ => format!("{};q={:.1}", language, (1.0 - index as f32 * 0.1).max(0.1)),
+        })
+        .collect();
+
+    Some(weighted.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{accept_language_of, locale_of};
+
+    #[test]
+    fn client_locale() {
+        let languages = vec!["it".to_string(), "pt-BR".into(), "en".into()];
+        assert_eq!(
+            locale_of(&languages),
+            (Some("it".into()), Some("BR".into()))
+        );
+        assert_eq!(
+            accept_language_of(&languages).unwrap(),
+            "it, pt-BR;q=0.9, en;q=0.8"
+        );
+
+        assert_eq!(locale_of(&["zh-Hant-TW".into()]).1.as_deref(), Some("TW"));
+        assert_eq!(locale_of(&[]), (None, None));
+        assert_eq!(accept_language_of(&[]), None);
+    }
+}
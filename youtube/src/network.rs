@@ -0,0 +1,113 @@
+//! Network usage policies applied by downloads and streams.
+//!
+//! The host tells the plugin whether the current connection is metered by
+//! registering a [`Connectivity`] implementation. Without one, every
+//! connection is considered unmetered and policies have no effect.
+
+use std::{fmt, sync::RwLock};
+
+/// Connectivity state supplied by the host
+pub trait Connectivity: Send + Sync {
+    /// Whether the current connection is metered (mobile data, hotspot, ...)
+    fn is_metered(&self) -> bool;
+}
+
+/// Policies evaluated on metered connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NetworkPolicy {
+    /// Refuses to start downloads on metered connections
+    pub unmetered_downloads_only: bool,
+    /// Highest source audio bitrate streamed on metered connections, in kbit/s
+    pub metered_stream_bitrate: Option<u32>,
+}
+
+/// Error returned when an operation is refused by the network policy
+#[derive(Debug)]
+pub enum NetworkError {
+    Metered,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::Metered => write!(f, "Downloads are disabled on metered connections"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+static CONNECTIVITY: RwLock<Option<Box<dyn Connectivity>>> = RwLock::new(None);
+static POLICY: RwLock<NetworkPolicy> = RwLock::new(NetworkPolicy {
+    unmetered_downloads_only: false,
+    metered_stream_bitrate: None,
+});
+
+/// Registers the source of the connectivity state
+pub fn set_connectivity(connectivity: impl Connectivity + 'static) {
+    *CONNECTIVITY.write().unwrap() = Some(Box::new(connectivity));
+}
+
+/// Replaces the network policy
+pub fn set_policy(policy: NetworkPolicy) {
+    *POLICY.write().unwrap() = policy;
+}
+
+/// Returns the network policy currently applied
+pub fn policy() -> NetworkPolicy {
+    *POLICY.read().unwrap()
+}
+
+fn is_metered() -> bool {
+    match &*CONNECTIVITY.read().unwrap() {
+        Some(connectivity) => connectivity.is_metered(),
+        None => false,
+    }
+}
+
+/// Checks whether a download can start on the current connection
+pub(crate) fn check_download() -> Result<(), NetworkError> {
+    check_download_for(&policy(), is_metered())
+}
+
+fn check_download_for(policy: &NetworkPolicy, metered: bool) -> Result<(), NetworkError> {
+    if policy.unmetered_downloads_only && metered {
+        return Err(NetworkError::Metered);
+    }
+
+    Ok(())
+}
+
+/// Source bitrate cap for streams on the current connection
+pub(crate) fn stream_bitrate() -> Option<u32> {
+    stream_bitrate_for(&policy(), is_metered())
+}
+
+fn stream_bitrate_for(policy: &NetworkPolicy, metered: bool) -> Option<u32> {
+    if metered {
+        policy.metered_stream_bitrate
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_download_for, stream_bitrate_for, NetworkPolicy};
+
+    #[test]
+    fn metered_policies() {
+        let policy = NetworkPolicy {
+            unmetered_downloads_only: true,
+            metered_stream_bitrate: Some(64),
+        };
+        assert!(check_download_for(&policy, false).is_ok());
+        assert_eq!(stream_bitrate_for(&policy, false), None);
+        assert!(check_download_for(&policy, true).is_err());
+        assert_eq!(stream_bitrate_for(&policy, true), Some(64));
+
+        let default = NetworkPolicy::default();
+        assert!(check_download_for(&default, true).is_ok());
+        assert_eq!(stream_bitrate_for(&default, true), None);
+    }
+}
@@ -0,0 +1,249 @@
+//! Resolution of playlist and album links.
+//!
+//! A query holding the link of a playlist is sent to the InnerTube browse
+//! endpoint instead of the search one, and answered with every track of the
+//! playlist. Albums are playlists too, with `OLAK5uy_` ids, linked from YouTube
+//! Music album pages. Playlists found in search results aren't tracks, so they
+//! are listed apart, by `YouTube::playlists`, with the link resolving them.
+
+use crate::{
+    artwork, events, read_artist_name_from, read_duration, read_track_id, read_track_name,
+    read_track_thumbnail, trace::Bundle, YTItemParser, ARTIST_THUMBNAIL_SIZE, SOURCE_URL,
+};
+use phosphorus_core::plugin_manager::{error::ParseError, query::QueryResultData};
+use serde_json::Value;
+use std::error::Error;
+
+const PLAYLIST_URL: &'static str = "https://youtube.com/playlist";
+/// Prefix of the ids of auto-generated mixes, that never end
+const MIX_PREFIX: &'static str = "RD";
+/// Prefix turning a playlist id into a browse id
+const BROWSE_PREFIX: &'static str = "VL";
+
+/// Playlist found in search results
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Playlist {
+    pub id: String,
+    pub title: String,
+    /// Number of tracks, if shown
+    pub track_count: Option<u32>,
+}
+
+impl Playlist {
+    /// Link that, used as query, returns every track of the playlist
+    pub fn url(&self) -> String {
+        format!("{}?list={}", PLAYLIST_URL, self.id)
+    }
+}
+
+/// Returns the browse id of the playlist linked by `query`, if it's a
+/// playlist link. Watch links inside a playlist resolve to the playlist,
+/// except for mixes.
+pub(crate) fn browse_id(query: &str) -> Option<String> {
+    let url = reqwest::Url::parse(query.trim()).ok()?;
+    let host = url.host_str()?;
+    if !(host == "youtu.be" || host == "youtube.com" || host.ends_with(".youtube.com")) {
+        return None;
+    }
+
+    let (_, id) = url.query_pairs().find(|(key, _)| key == "list")?;
+    if id.is_empty() || id.starts_with(MIX_PREFIX) {
+        return None;
+    }
+
+    Some(format!("{}{}", BROWSE_PREFIX, id))
+}
+
+/// Reads a `playlistRenderer` of the search results
+pub(crate) fn read_playlist(source: &Value) -> Result<Playlist, Box<dyn Error>> {
+    Ok(Playlist {
+        id: source.read_str("playlistId")?.into(),
+        title: source.read("title")?.read_str("simpleText")?.into(),
+        track_count: source
+            .read_str("videoCount")
+            .ok()
+            .and_then(|count| count.parse().ok()),
+    })
+}
+
+/// Returns `true` if `json` is a browse response, or a browse continuation
+pub(crate) fn is_browse_response(json: &Value) -> bool {
+    json.get("onResponseReceivedActions").is_some()
+        || json
+            .get("contents")
+            .and_then(|contents| contents.get("twoColumnBrowseResultsRenderer"))
+            .is_some()
+}
+
+/// Parses the tracks listed by a page of a playlist, along with the token of
+/// the following page, if any. Deleted and private videos are skipped, and so
+/// are malformed entries, like lenient parsing does for search results.
+pub(crate) fn parse_page(
+    json: &Value,
+    bundle: &Bundle,
+) -> Result<(Vec<QueryResultData>, Option<String>), Box<dyn Error>> {
+    if let Some(error) = json.get("error") {
+        bundle.decision(&format!("Playlist failed: {}", error));
+        return Err(Box::new(ParseError::InvalidResponseText));
+    }
+
+    let mut items = vec![];
+    let mut continuation = None;
+    let mut unplayable = 0;
+    let mut malformed = 0;
+    for entry in read_entries(json)? {
+        if let Some(renderer) = entry.get("continuationItemRenderer") {
+            continuation = renderer
+                .read("continuationEndpoint")?
+                .read("continuationCommand")?
+                .read_str("token")
+                .ok()
+                .map(String::from);
+        } else if let Some(video) = entry.get("playlistVideoRenderer") {
+            if video.get("isPlayable").and_then(Value::as_bool) == Some(false) {
+                bundle.decision(&format!(
+                    "Skipped unplayable entry {}",
+                    read_track_id(video).unwrap_or_default()
+                ));
+                unplayable += 1;
+                continue;
+            }
+            match parse_entry(video) {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    bundle.decision(&format!(
+                        "Skipped malformed entry {}: {}",
+                        read_track_id(video).unwrap_or_default(),
+                        err
+                    ));
+                    malformed += 1;
+                }
+            }
+        } else {
+            bundle.decision(&format!("Skipped entry {}", crate::item_kind(entry)));
+        }
+    }
+    bundle.decision(&format!("Parsed {} playlist entries", items.len()));
+    if unplayable > 0 {
+        events::warn_traced(
+            bundle,
+            format!("{} unplayable playlist entries skipped", unplayable),
+        );
+    }
+    if malformed > 0 {
+        events::warn_traced(
+            bundle,
+            format!("{} malformed playlist entries skipped", malformed),
+        );
+    }
+
+    Ok((items, continuation))
+}
+
+/// Returns the entries of a playlist page, or of a continuation page
+fn read_entries(json: &Value) -> Result<&Vec<Value>, Box<dyn Error>> {
+    let entries = match json.get("onResponseReceivedActions") {
+        Some(actions) => actions
+            .read_first()?
+            .read("appendContinuationItemsAction")?
+            .read("continuationItems")?,
+        None => json
+            .read("contents")?
+            .read("twoColumnBrowseResultsRenderer")?
+            .read("tabs")?
+            .read_first()?
+            .read("tabRenderer")?
+            .read("content")?
+            .read("sectionListRenderer")?
+            .read("contents")?
+            .read_first()?
+            .read("itemSectionRenderer")?
+            .read("contents")?
+            .read_first()?
+            .read("playlistVideoListRenderer")?
+            .read("contents")?,
+    };
+
+    match entries.as_array() {
+        Some(entries) => Ok(entries),
+        None => Err(Box::new(ParseError::InvalidResponseText)),
+    }
+}
+
+/// Parses a `playlistVideoRenderer`. Entries don't show the channel avatar,
+/// so the artist thumbnail is the stock artwork of the video.
+fn parse_entry(source: &Value) -> Result<QueryResultData, Box<dyn Error>> {
+    let track_id = read_track_id(source)?;
+    let track_url = reqwest::Url::parse(&format!("{}?v={}", SOURCE_URL, track_id))?;
+
+    let (width, height) = ARTIST_THUMBNAIL_SIZE;
+    let artist_thumbnail = artwork::best_for(&artwork::artwork_sizes(track_id), width, height)
+        .map(|artwork| artwork.url.clone())
+        .ok_or_else(|| ParseError::JsonUnparsable("No artist thumbnail".into()))?;
+
+    Ok(QueryResultData::new(
+        track_id,
+        read_track_name(source)?,
+        track_url,
+        read_track_thumbnail(source)?,
+        read_artist_name_from(source, "shortBylineText")?,
+        artist_thumbnail,
+        read_duration(source)?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{browse_id, is_browse_response, parse_page, Bundle};
+
+    #[test]
+    fn playlist_links() {
+        assert_eq!(
+            browse_id("https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI")
+                .as_deref(),
+            Some("VLPLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI")
+        );
+        assert_eq!(
+            browse_id("https://music.youtube.com/playlist?list=OLAK5uy_k8q2Mrk2Vl5YdGyrM8L3hoOmWQ")
+                .as_deref(),
+            Some("VLOLAK5uy_k8q2Mrk2Vl5YdGyrM8L3hoOmWQ"),
+            "Album links"
+        );
+        assert_eq!(
+            browse_id("https://youtu.be/dQw4w9WgXcQ?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI")
+                .as_deref(),
+            Some("VLPLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI")
+        );
+        assert_eq!(
+            browse_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=RDdQw4w9WgXcQ"),
+            None,
+            "Mixes never end"
+        );
+        assert_eq!(browse_id("https://example.com/playlist?list=PL1"), None);
+        assert_eq!(browse_id("lofi playlist"), None);
+    }
+
+    #[test]
+    fn playlist_pages() {
+        let json =
+            serde_json::from_str(include_str!("../tests/corpus/2022-10-playlist.json")).unwrap();
+        assert!(is_browse_response(&json));
+        let (items, continuation) = parse_page(&json, &Bundle::default()).unwrap();
+        assert_eq!(items.len(), 2, "The deleted video is skipped");
+        assert!(continuation.is_some());
+
+        let json = serde_json::from_str(include_str!(
+            "../tests/corpus/2022-11-playlist-broken-entry.json"
+        ))
+        .unwrap();
+        let (items, _) = parse_page(&json, &Bundle::default()).unwrap();
+        assert_eq!(items.len(), 1, "The entry without duration is skipped");
+
+        let json = serde_json::json!({ "onResponseReceivedActions": [{
+            "appendContinuationItemsAction": { "continuationItems": [] }
+        }]});
+        assert!(is_browse_response(&json));
+        let (items, continuation) = parse_page(&json, &Bundle::default()).unwrap();
+        assert!(items.is_empty() && continuation.is_none());
+    }
+}
@@ -0,0 +1,463 @@
+//! Downloads and streams reporting typed progress events.
+//!
+//! `download_fn` and `stream_fn` hand the bare process over to the caller.
+//! [`download`] and [`stream`] start the same processes, but keep them on a
+//! background thread that parses their progress output and sends
+//! [`Progress`] events through a channel. The returned [`Transfer`] also holds
+//! a [`CancelHandle`] that kills the whole process tree.
+
+use crate::{
+    age, concurrency,
+    events::{self, Event},
+    guardrails, hooks,
+    stats::{StatsReader, StreamStats},
+};
+use serde_json::Value;
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Read},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Prefix of the progress lines printed by `yt-dlp`
+const PROGRESS_PREFIX: &'static str = "[phosphorus]";
+/// Progress line template, see [`parse_download_line`]
+const PROGRESS_TEMPLATE: &'static str = "download:[phosphorus] %(progress.downloaded_bytes)s \
+    %(progress.total_bytes)s %(progress.total_bytes_estimate)s %(progress.speed)s %(progress.eta)s";
+/// Prefix of the line printed by `yt-dlp` once the file is in place
+const COMPLETION_PREFIX: &'static str = "[phosphorus-done]";
+/// Completion line template, the fields read by `hooks` as JSON
+const COMPLETION_TEMPLATE: &'static str = "after_move:[phosphorus-done] \
+    %(.{filepath,title,artist,album,uploader,webpage_url})j";
+/// Reason of a download that yt-dlp skipped without printing why
+const SKIPPED: &'static str =
+    "yt-dlp skipped the video, it doesn't respect the guardrails or the age policy";
+
+/// Event sent while a download or a stream runs. `Finished`, `Failed` and
+/// `Cancelled` are always the last event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// The media is being downloaded
+    Downloading {
+        /// Completion, from 0 to 100, if the size is known
+        percent: Option<f32>,
+        /// Download speed in bytes/sec
+        speed: Option<f64>,
+        eta: Option<Duration>,
+    },
+    /// The stream is being transcoded
+    Transcoding(StreamStats),
+    Finished,
+    /// The process failed, with the reason it reported
+    Failed(String),
+    Cancelled,
+}
+
+/// Handle that kills a running transfer
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    pid: u32,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    /// Kills the processes of the transfer, including the ones they spawned.
+    /// A `Cancelled` event is sent once they're gone.
+    pub fn cancel(&self) {
+        if !self.cancelled.swap(true, Ordering::SeqCst) {
+            kill_tree(self.pid);
+        }
+    }
+}
+
+/// A running download or stream
+pub struct Transfer {
+    /// Progress of the transfer
+    pub events: Receiver<Progress>,
+    pub cancel: CancelHandle,
+}
+
+/// Starts downloading the media at `url` into `file_name.[ext]`, like
+/// `download_fn` does.
+pub fn download(url: &str, file_name: &str) -> Result<Transfer, Box<dyn Error>> {
+    let mut command = crate::download_command(url, file_name)?;
+    command.args([
+        "--newline",
+        "--progress",
+        "--progress-template",
+        PROGRESS_TEMPLATE,
+        "--print",
+        COMPLETION_TEMPLATE,
+    ]);
+
+    let url = url.to_string();
+    let transfer = spawn(command, "yt-dlp", move |stdout, sender| {
+        let mut report = Report {
+            download: Some(url),
+            ..Report::default()
+        };
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(progress) = parse_download_line(&line) {
+                let _ = sender.send(progress);
+            } else if let Some(completion) = line.strip_prefix(COMPLETION_PREFIX) {
+                report.details = serde_json::from_str(completion.trim()).ok();
+            } else if report.refusal.is_none() {
+                report.refusal = refusal(&line);
+            }
+        }
+        // `--print` implies `--quiet`, so yt-dlp usually skips a video without
+        // saying why, and exits with success. Skipped videos are never moved
+        // in place, so they print no completion line.
+        if report.details.is_none() && report.refusal.is_none() {
+            report.refusal = Some(SKIPPED.into());
+        }
+        report
+    })?;
+    guardrails::count();
+
+    Ok(transfer)
+}
+
+/// Starts streaming the media at `url` into `file_name`, like `stream_fn`
+/// does.
+pub fn stream(url: &str, file_name: &str) -> Result<Transfer, Box<dyn Error>> {
+    let command = crate::stream_command(url, file_name)?;
+
+    spawn(command, "ffmpeg", |stdout, sender| {
+        for stats in StatsReader::new(stdout) {
+            let _ = sender.send(Progress::Transcoding(stats));
+        }
+        Report::default()
+    })
+}
+
+/// Spawns `command` in its own process group and watches it on a background
+/// thread. `report` reads the process stdout until it's closed.
+fn spawn(
+    mut command: Command,
+    binary: &str,
+    report: impl FnOnce(Box<dyn Read + Send>, &Sender<Progress>) -> Report + Send + 'static,
+) -> Result<Transfer, Box<dyn Error>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Lets `kill_tree` reach every process spawned by `command`
+        command.process_group(0);
+    }
+
+    let permit = concurrency::acquire()?;
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| events::spawn_error(binary, err))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let cancel = CancelHandle {
+        pid: child.id(),
+        cancelled: Arc::new(AtomicBool::new(false)),
+    };
+    let cancelled = cancel.cancelled.clone();
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    thread::spawn(move || {
+        // Collected aside, so a verbose process can't block on a full pipe
+        let errors = stderr.map(|stderr| {
+            thread::spawn(move || {
+                let mut text = String::new();
+                let _ = BufReader::new(stderr).read_to_string(&mut text);
+                text
+            })
+        });
+        let report = match stdout {
+            Some(stdout) => report(Box::new(stdout), &sender),
+            None => Report::default(),
+        };
+        let errors = errors
+            .and_then(|errors| errors.join().ok())
+            .unwrap_or_default();
+
+        let outcome = match outcome(&mut child, &errors, &cancelled) {
+            // The extractor exits with success when it skips a video
+            Progress::Finished => match errors.lines().find_map(refusal).or(report.refusal) {
+                Some(reason) => Progress::Failed(reason),
+                None => Progress::Finished,
+            },
+            outcome => outcome,
+        };
+        drop(permit);
+        match (&outcome, report.download) {
+            (Progress::Finished, Some(url)) => events::emit(Event::DownloadFinished(url)),
+            (Progress::Failed(reason), Some(url)) => {
+                events::emit(Event::DownloadFailed(url, reason.clone()))
+            }
+            _ => (),
+        }
+        let finished = outcome == Progress::Finished;
+        let _ = sender.send(outcome);
+        if let (true, Some(details)) = (finished, report.details) {
+            hooks::completed(&details);
+        }
+    });
+
+    Ok(Transfer {
+        events: receiver,
+        cancel,
+    })
+}
+
+/// What a process reported on stdout
+#[derive(Default)]
+struct Report {
+    /// Fields of the completed download, for the `hooks`
+    details: Option<Value>,
+    /// Reason the download was skipped, although the process succeeded
+    refusal: Option<String>,
+    /// Url of the download, for the `events`
+    download: Option<String>,
+}
+
+/// Reason the extractor gave for skipping a video, because of the
+/// guardrails or of the age policy
+fn refusal(line: &str) -> Option<String> {
+    guardrails::refusal(line).or_else(|| age::refusal(line))
+}
+
+/// Waits for `child` and turns its exit status into the last event
+fn outcome(child: &mut Child, errors: &str, cancelled: &AtomicBool) -> Progress {
+    let status = child.wait();
+    if cancelled.load(Ordering::SeqCst) {
+        return Progress::Cancelled;
+    }
+
+    match status {
+        Ok(status) if status.success() => Progress::Finished,
+        Ok(status) => Progress::Failed(failure_reason(errors).unwrap_or(status.to_string())),
+        Err(err) => Progress::Failed(err.to_string()),
+    }
+}
+
+/// Last error reported on stderr, or its last line
+pub(crate) fn failure_reason(errors: &str) -> Option<String> {
+    let lines: Vec<&str> = errors
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    lines
+        .iter()
+        .rev()
+        .find(|line| line.starts_with("ERROR:"))
+        .or(lines.last())
+        .map(|line| line.trim().to_string())
+}
+
+/// Parses a line printed with `PROGRESS_TEMPLATE`: downloaded bytes, total
+/// bytes, estimated total bytes, speed and ETA, each one possibly `NA`.
+fn parse_download_line(line: &str) -> Option<Progress> {
+    let fields: Vec<Option<f64>> = line
+        .strip_prefix(PROGRESS_PREFIX)?
+        .split_whitespace()
+        .map(|field| field.parse().ok())
+        .collect();
+    if fields.len() != 5 {
+        return None;
+    }
+
+    let total = fields[1].or(fields[2]).filter(|total| *total > 0.0);
+    let percent = match (fields[0], total) {
+        (Some(downloaded), Some(total)) => Some((downloaded / total * 100.0).min(100.0) as f32),
+        _ => None,
+    };
+
+    Some(Progress::Downloading {
+        percent,
+        speed: fields[3],
+        eta: fields[4].map(Duration::from_secs_f64),
+    })
+}
+
+#[cfg(unix)]
+fn kill_tree(pid: u32) {
+    // The group id is the pid of its first process, see `spawn`
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGTERM);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_tree(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/T", "/F", "/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(test)]
+mod test {
+    use super::{failure_reason, parse_download_line, Progress};
+    use std::time::Duration;
+
+    #[test]
+    fn download_lines() {
+        assert_eq!(
+            parse_download_line("[phosphorus] 1048576 4194304 NA 524288.0 6"),
+            Some(Progress::Downloading {
+                percent: Some(25.0),
+                speed: Some(524288.0),
+                eta: Some(Duration::from_secs(6)),
+            })
+        );
+        assert_eq!(
+            parse_download_line("[phosphorus] 1048576 NA 2097152 NA NA"),
+            Some(Progress::Downloading {
+                percent: Some(50.0),
+                speed: None,
+                eta: None,
+            }),
+            "Falls back to the estimated size"
+        );
+        assert_eq!(
+            parse_download_line("[youtube] dQw4w9WgXcQ: Downloading webpage"),
+            None
+        );
+    }
+
+    #[test]
+    fn failure_reasons() {
+        let errors = "WARNING: unable to extract uploader\nERROR: [youtube] dQw4w9WgXcQ: Video unavailable\n\n";
+        assert_eq!(
+            failure_reason(errors).unwrap(),
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable"
+        );
+        assert_eq!(
+            failure_reason("file:out.mp3: Permission denied\n").unwrap(),
+            "file:out.mp3: Permission denied"
+        );
+        assert_eq!(failure_reason(""), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fake_downloads() {
+        use crate::events::{self, Event};
+        use crate::fake::{self, Script};
+        use std::sync::{Arc, Mutex};
+
+        let fakes = fake::install();
+        let received = Arc::new(Mutex::new(vec![]));
+        let sink = received.clone();
+        let subscription = events::subscribe(move |event| {
+            if let Event::DownloadFinished(_) | Event::DownloadFailed(..) = event {
+                sink.lock().unwrap().push(event.clone());
+            }
+        });
+        fakes.yt_dlp(
+            Script::new()
+                .stdout("[youtube] dQw4w9WgXcQ: Downloading webpage")
+                .stdout("[phosphorus] 512 1024 NA 256.0 2")
+                .stdout("[phosphorus] 1024 1024 NA 256.0 0")
+                .stdout(r#"[phosphorus-done] {"filepath": "out.mp3", "title": "Never"}"#),
+        );
+        let transfer = super::download("https://youtube.com/watch?v=dQw4w9WgXcQ", "out").unwrap();
+        let events: Vec<Progress> = transfer.events.iter().collect();
+        assert_eq!(events.len(), 3, "{:?}", events);
+        assert!(matches!(
+            events[0],
+            Progress::Downloading {
+                percent: Some(percent),
+                ..
+            } if percent == 50.0
+        ));
+        assert_eq!(events[2], Progress::Finished);
+        assert!(fakes.args("yt-dlp").contains(&"--newline".to_string()));
+
+        fakes.yt_dlp(
+            Script::new()
+                .stderr("ERROR: [youtube] dQw4w9WgXcQ: Video unavailable")
+                .exit(1),
+        );
+        let transfer = super::download("https://youtube.com/watch?v=dQw4w9WgXcQ", "out").unwrap();
+        assert_eq!(
+            transfer.events.iter().last(),
+            Some(Progress::Failed(
+                "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable".into()
+            ))
+        );
+
+        events::unsubscribe(subscription);
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                Event::DownloadFinished("https://youtube.com/watch?v=dQw4w9WgXcQ".into()),
+                Event::DownloadFailed(
+                    "https://youtube.com/watch?v=dQw4w9WgXcQ".into(),
+                    "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable".into()
+                )
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refused_downloads() {
+        use crate::fake::{self, Script};
+
+        let fakes = fake::install();
+        let refused =
+            "[download] Never Gonna Give You Up does not pass filter (duration <= 60), skipping ..";
+        fakes.yt_dlp(Script::new().stdout(refused));
+        let transfer = super::download("https://youtube.com/watch?v=dQw4w9WgXcQ", "out").unwrap();
+        assert_eq!(
+            transfer.events.iter().last(),
+            Some(Progress::Failed(refused.into()))
+        );
+
+        fakes.yt_dlp(Script::new().stderr(
+            "[download] File is larger than max-filesize (4194304 bytes > 1048576 bytes). Aborting.",
+        ));
+        let transfer = super::download("https://youtube.com/watch?v=dQw4w9WgXcQ", "out").unwrap();
+        assert!(matches!(
+            transfer.events.iter().last(),
+            Some(Progress::Failed(reason)) if reason.contains("max-filesize")
+        ));
+
+        // `--print` implies `--quiet`: yt-dlp usually skips without a word
+        fakes.yt_dlp(Script::new());
+        let transfer = super::download("https://youtube.com/watch?v=dQw4w9WgXcQ", "out").unwrap();
+        assert_eq!(
+            transfer.events.iter().last(),
+            Some(Progress::Failed(super::SKIPPED.into())),
+            "Nothing was downloaded"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancellation() {
+        use crate::fake::{self, Script};
+
+        let fakes = fake::install();
+        fakes.yt_dlp(Script::new().stdout("[phosphorus] 0 1024 NA NA NA").hang());
+        let transfer = super::download("https://youtube.com/watch?v=dQw4w9WgXcQ", "out").unwrap();
+        assert!(matches!(
+            transfer.events.recv().unwrap(),
+            Progress::Downloading { .. }
+        ));
+
+        transfer.cancel.cancel();
+        assert_eq!(
+            transfer.events.recv_timeout(Duration::from_secs(5)),
+            Ok(Progress::Cancelled),
+            "The hanging child is killed along with the fake"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Runtime statistics of a stream session.
+//!
+//! The transcoder started by `stream_fn` reports its progress on stdout, one
+//! `key=value` block every half a second. [`StatsReader`] turns those blocks
+//! into [`StreamStats`] snapshots, which tell whether hiccups come from the
+//! network (the transcoder runs slower than real time while using little CPU)
+//! or from the machine.
+
+use std::{
+    io::{BufRead, BufReader, Lines, Read},
+    time::{Duration, Instant},
+};
+
+/// Transcoding speed under which a progress block counts as a stall
+const STALL_SPEED: f32 = 1.0;
+
+/// Snapshot of a stream session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamStats {
+    /// Bytes written to the output file so far
+    pub total_size: u64,
+    /// Media time transcoded so far
+    pub out_time: Duration,
+    /// Transcoding speed relative to real time
+    pub speed: f32,
+    /// Output bitrate in kbit/s
+    pub bitrate: f32,
+    /// Average bytes/sec written to the output since the session started. The
+    /// transcoder doesn't report what it reads from the source.
+    pub throughput: f64,
+    /// Number of progress blocks reported below real time speed
+    pub stalls: u32,
+    /// Whether the transcoder reached the end of the stream
+    pub finished: bool,
+}
+
+/// Iterator over the progress reported by the transcoder on `source`,
+/// usually the stdout of the process returned by `stream_fn`
+pub struct StatsReader<R: Read> {
+    lines: Lines<BufReader<R>>,
+    stats: StreamStats,
+    started: Instant,
+}
+
+impl<R: Read> StatsReader<R> {
+    pub fn new(source: R) -> Self {
+        StatsReader {
+            lines: BufReader::new(source).lines(),
+            stats: StreamStats::default(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for StatsReader<R> {
+    type Item = StreamStats;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stats.finished {
+            return None;
+        }
+
+        while let Some(Ok(line)) = self.lines.next() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+
+            match key {
+                "total_size" => self.stats.total_size = value.parse().unwrap_or(0),
+                "out_time_us" => {
+                    self.stats.out_time = Duration::from_micros(value.parse().unwrap_or(0))
+                }
+                "speed" => self.stats.speed = value.trim_end_matches('x').parse().unwrap_or(0.0),
+                "bitrate" => {
+                    self.stats.bitrate = value.trim_end_matches("kbits/s").parse().unwrap_or(0.0)
+                }
+                "progress" => {
+                    let elapsed = self.started.elapsed().as_secs_f64();
+                    if elapsed > 0.0 {
+                        self.stats.throughput = self.stats.total_size as f64 / elapsed;
+                    }
+                    if self.stats.speed < STALL_SPEED && value == "continue" {
+                        self.stats.stalls += 1;
+                    }
+                    self.stats.finished = value == "end";
+                    return Some(self.stats.clone());
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+/// CPU time consumed so far by the process `pid`, e.g. the transcoder
+/// returned by `stream_fn`. Only available on Linux.
+pub fn cpu_time(pid: u32) -> Option<Duration> {
+    // Linux reports process times in USER_HZ, which is 100 on every platform
+    const TICKS_PER_SEC: u64 = 100;
+
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The process name may contain spaces, fields are counted after it
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+
+    Some(Duration::from_millis(
+        (utime + stime) * 1000 / TICKS_PER_SEC,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::StatsReader;
+    use std::time::Duration;
+
+    #[test]
+    fn progress_blocks() {
+        let output = "bitrate= 128.0kbits/s\ntotal_size=16384\nout_time_us=1000000\nspeed=2.05x\nprogress=continue\n\
+                      bitrate= 128.1kbits/s\ntotal_size=24576\nout_time_us=1500000\nspeed=0.5x\nprogress=continue\n\
+                      bitrate=N/A\ntotal_size=32768\nout_time_us=2000000\nspeed=N/A\nprogress=end\n";
+        let stats: Vec<_> = StatsReader::new(output.as_bytes()).collect();
+
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0].total_size, 16384);
+        assert_eq!(stats[0].out_time, Duration::from_secs(1));
+        assert_eq!(stats[0].speed, 2.05);
+        assert_eq!(stats[0].bitrate, 128.0);
+        assert_eq!(stats[0].stalls, 0);
+        assert_eq!(
+            stats[1].stalls, 1,
+            "Slower than real time counts as a stall"
+        );
+        assert!(stats[2].finished);
+        assert_eq!(stats[2].stalls, 1, "The last block never counts as a stall");
+    }
+}
@@ -0,0 +1,227 @@
+//! Debug mode recording the request, the raw response and the parser decisions
+//! of an operation, so that breakages caused by YouTube layout changes can be
+//! reported with actionable evidence.
+//!
+//! Every traced operation produces a directory `youtube-<unix millis>-<pid>-<n>`
+//! under the trace directory, `n` counting the operations traced by the
+//! process, holding `request.txt`, `response.txt`, `body.txt` and
+//! `decisions.txt`. Cookies and credentials are never written: sensitive
+//! headers and the identifying fields of bodies, like `visitorData`, are
+//! redacted. The directory can be zipped and attached to
+//! an issue as-is.
+//!
+//! [`request`] returns the [`Bundle`] of the operation, that its following
+//! steps write to, so operations traced at the same time never mix.
+//!
+//! Tracing is enabled with [`enable`] or by setting the `PHOSPHORUS_TRACE_DIR`
+//! environment variable.
+
+use regex::Regex;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const TRACE_DIR_VAR: &'static str = "PHOSPHORUS_TRACE_DIR";
+const REDACTED: &'static str = "<redacted>";
+/// Headers whose values are never written to a trace
+const SENSITIVE_HEADERS: [&'static str; 5] = [
+    "cookie",
+    "set-cookie",
+    "authorization",
+    "x-goog-authuser",
+    "x-goog-visitor-id",
+];
+/// Fields of request and response bodies whose values are never written to a
+/// trace, some of them only found in the configuration of HTML pages
+const SENSITIVE_FIELDS: [&'static str; 8] = [
+    "visitorData",
+    "datasyncId",
+    "delegatedSessionId",
+    "idToken",
+    "sessionId",
+    "VISITOR_DATA",
+    "DATASYNC_ID",
+    "ID_TOKEN",
+];
+
+static TRACE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+/// Bundles started by the process, so that names never collide
+static BUNDLES: AtomicU64 = AtomicU64::new(0);
+
+/// Enables tracing, writing bundles into `dir`
+pub fn enable(dir: impl Into<PathBuf>) {
+    *TRACE_DIR.lock().unwrap() = Some(dir.into());
+}
+
+/// Disables tracing. Has no effect on `PHOSPHORUS_TRACE_DIR`.
+pub fn disable() {
+    *TRACE_DIR.lock().unwrap() = None;
+}
+
+fn trace_dir() -> Option<PathBuf> {
+    match &*TRACE_DIR.lock().unwrap() {
+        Some(dir) => Some(dir.clone()),
+        None => std::env::var_os(TRACE_DIR_VAR).map(PathBuf::from),
+    }
+}
+
+/// Directory recording a traced operation. Its steps do nothing when tracing
+/// is disabled.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bundle {
+    dir: Option<PathBuf>,
+}
+
+impl Bundle {
+    /// Records the status line and headers of a response
+    pub(crate) fn response(&self, resp: &reqwest::Response) {
+        if let Some(dir) = &self.dir {
+            let text = format!(
+                "{} {}\n{}",
+                resp.status(),
+                resp.url(),
+                headers(resp.headers())
+            );
+            let _ = fs::write(dir.join("response.txt"), text);
+        }
+    }
+
+    /// Records the body of a response
+    pub(crate) fn body(&self, text: &str) {
+        if let Some(dir) = &self.dir {
+            let _ = fs::write(dir.join("body.txt"), scrub(text));
+        }
+    }
+
+    /// Appends a parser decision
+    pub(crate) fn decision(&self, message: &str) {
+        if let Some(dir) = &self.dir {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join("decisions.txt"));
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", message);
+            }
+        }
+    }
+}
+
+/// Starts a new bundle for `req`, returning it for the following steps
+pub(crate) fn request(req: &reqwest::Request) -> Bundle {
+    let dir = match trace_dir() {
+        Some(dir) => dir,
+        None => return Bundle::default(),
+    };
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let bundle = dir.join(format!(
+        "youtube-{}-{}-{}",
+        millis,
+        std::process::id(),
+        BUNDLES.fetch_add(1, Ordering::Relaxed)
+    ));
+    if fs::create_dir_all(&bundle).is_err() {
+        return Bundle::default();
+    }
+
+    let mut text = format!("{} {}\n", req.method(), req.url());
+    text.push_str(&headers(req.headers()));
+    if let Some(body) = req.body().and_then(|body| body.as_bytes()) {
+        text.push('\n');
+        text.push_str(&scrub(&String::from_utf8_lossy(body)));
+    }
+    let _ = fs::write(bundle.join("request.txt"), text);
+
+    Bundle { dir: Some(bundle) }
+}
+
+/// Formats `headers` one per line, redacting sensitive values
+fn headers(headers: &reqwest::header::HeaderMap) -> String {
+    let mut text = String::new();
+    for (name, value) in headers {
+        let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+            REDACTED
+        } else {
+            value.to_str().unwrap_or("<binary>")
+        };
+        text.push_str(&format!("{}: {}\n", name, value));
+    }
+
+    text
+}
+
+/// Redacts the values of the `SENSITIVE_FIELDS` of `text`, a JSON body or an
+/// HTML page embedding JSON
+fn scrub(text: &str) -> String {
+    let fields = Regex::new(&format!(
+        r#""({})"(\s*:\s*)"[^"]*""#,
+        SENSITIVE_FIELDS.join("|")
+    ))
+    .unwrap();
+    fields
+        .replace_all(text, format!(r#""$1"$2"{}""#, REDACTED))
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    #[test]
+    fn sensitive_headers_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert("cookie", HeaderValue::from_static("SID=secret"));
+        headers.insert("user-agent", HeaderValue::from_static("test"));
+
+        let text = super::headers(&headers);
+        assert!(!text.contains("secret"), "Cookie value leaked: {}", text);
+        assert!(text.contains("cookie: <redacted>"));
+        assert!(text.contains("user-agent: test"));
+    }
+
+    #[test]
+    fn sensitive_fields_are_redacted() {
+        let body = r#"{"responseContext":{"visitorData":"CgtGeDR1","serviceTrackingParams":[]},"estimatedResults":"3"}"#;
+        assert_eq!(
+            super::scrub(body),
+            r#"{"responseContext":{"visitorData":"<redacted>","serviceTrackingParams":[]},"estimatedResults":"3"}"#
+        );
+
+        let page = r#"ytcfg.set({"VISITOR_DATA": "CgtGeDR1", "ID_TOKEN":"QUFFLUhq"});"#;
+        let scrubbed = super::scrub(page);
+        assert!(!scrubbed.contains("CgtGeDR1") && !scrubbed.contains("QUFFLUhq"));
+        assert!(scrubbed.contains(r#""VISITOR_DATA": "<redacted>""#));
+    }
+
+    #[test]
+    fn operations_keep_their_bundle() {
+        let dir = std::env::temp_dir().join(format!("phosphorus-trace-{}", std::process::id()));
+        let bundles: Vec<super::Bundle> = (0..2)
+            .map(|n| {
+                let dir = dir.join(n.to_string());
+                std::fs::create_dir_all(&dir).unwrap();
+                super::Bundle { dir: Some(dir) }
+            })
+            .collect();
+
+        bundles[0].decision("Parsed 3 items");
+        bundles[1].decision("Last page of results");
+        bundles[0].decision("Parsed 2 items");
+        let decisions = |n: usize| {
+            std::fs::read_to_string(dir.join(n.to_string()).join("decisions.txt")).unwrap()
+        };
+        assert_eq!(decisions(0), "Parsed 3 items\nParsed 2 items\n");
+        assert_eq!(decisions(1), "Last page of results\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,389 @@
+//! Transcode stage of the stream pipeline.
+//!
+//! Streams are re-encoded by `ffmpeg`, that can apply an audio filter chain and
+//! a speed adjustment and a loudness [`Normalization`] on the way, and stop
+//! after a given time for sleep timers. The host sets the [`TranscodeOptions`]
+//! for the next stream session with [`set_options`], which validates them
+//! first.
+
+use crate::{binaries, download::AudioFormat};
+use plugin_tools::codec::{self, UnknownContainer};
+use std::{fmt, ops::RangeInclusive, process::Command, sync::RwLock, time::Duration};
+
+/// Playback speeds accepted for streams
+const SPEED_RANGE: RangeInclusive<f32> = 0.5..=4.0;
+/// Highest factor a single `atempo` filter applies without artifacts
+const MAX_ATEMPO: f32 = 2.0;
+/// Options taking a file name, refused in any filter
+const FILE_OPTIONS: [&'static str; 3] = ["dumpfile", "filename", "file"];
+/// Length of the fade-out applied before a stream is stopped by `stop_after`
+const FADE_OUT: Duration = Duration::from_secs(10);
+
+/// Filters the host can use. Filters able to read or write files (`amovie`,
+/// `sendcmd`, `firequalizer`, ...) are deliberately left out.
+const ALLOWED_FILTERS: [&'static str; 25] = [
+    "acompressor",
+    "aecho",
+    "afade",
+    "alimiter",
+    "anequalizer",
+    "aresample",
+    "asetrate",
+    "atempo",
+    "bandpass",
+    "bass",
+    "compand",
+    "crossfeed",
+    "dynaudnorm",
+    "earwax",
+    "equalizer",
+    "extrastereo",
+    "highpass",
+    "loudnorm",
+    "lowpass",
+    "pan",
+    "rubberband",
+    "silenceremove",
+    "stereotools",
+    "superequalizer",
+    "treble",
+];
+
+/// Loudness profile of a stream, so content of different kinds plays at
+/// comfortable relative levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalization {
+    /// The source loudness is kept
+    #[default]
+    Off,
+    /// Normalized to -14 LUFS, the level of most streaming services
+    Music,
+    /// Compressed and normalized to -16 LUFS, for podcasts and talks
+    Voice,
+}
+
+impl Normalization {
+    const fn filter(&self) -> Option<&'static str> {
+        match self {
+            Normalization::Off => None,
+            Normalization::Music => Some("loudnorm=I=-14:TP=-1:LRA=11"),
+            Normalization::Voice => Some(
+                "acompressor=threshold=-21dB:ratio=3:attack=20:release=250,\
+                loudnorm=I=-16:TP=-1.5:LRA=7",
+            ),
+        }
+    }
+}
+
+/// Options of the transcode stage
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TranscodeOptions {
+    /// Audio filter chain, in `ffmpeg` syntax (e.g. `bass=g=3,crossfeed`)
+    pub audio_filter: Option<String>,
+    /// Playback speed applied with pitch correction, from 0.5 to 4.0. Useful
+    /// for spoken content when the host's audio backend can't resample.
+    pub speed: Option<f32>,
+    /// Length of the stream, after which the transcoder fades out and exits.
+    /// No process of the pipeline outlives it.
+    pub stop_after: Option<Duration>,
+    /// Codec the stream is encoded with. `Best` encodes to the codec the
+    /// extension of the file calls for, and keeps the source codec only when
+    /// no filter is applied and the container takes any codec (`.mka`).
+    /// Extensions without a known codec are refused.
+    pub format: AudioFormat,
+    /// Target bitrate, in kbit/s. Ignored by lossless codecs.
+    pub bitrate: Option<u32>,
+    /// Loudness profile, applied after the other filters
+    pub normalization: Normalization,
+}
+
+impl TranscodeOptions {
+    const fn new() -> Self {
+        TranscodeOptions {
+            audio_filter: None,
+            speed: None,
+            stop_after: None,
+            format: AudioFormat::Mp3,
+            bitrate: None,
+            normalization: Normalization::Off,
+        }
+    }
+
+    /// `ffmpeg` audio codec for these options, writing to `file_name`
+    fn codec(&self, file_name: &str) -> Result<&'static str, UnknownContainer> {
+        codec::codec(self.format, file_name, self.filter_chain().is_none())
+    }
+
+    /// Complete filter chain for these options
+    fn filter_chain(&self) -> Option<String> {
+        let mut filters = vec![];
+        if let Some(chain) = &self.audio_filter {
+            filters.push(chain.clone());
+        }
+        if let Some(mut speed) = self.speed {
+            while speed > MAX_ATEMPO {
+                filters.push(format!("atempo={}", MAX_ATEMPO));
+                speed /= MAX_ATEMPO;
+            }
+            filters.push(format!("atempo={}", speed));
+        }
+        if let Some(filter) = self.normalization.filter() {
+            filters.push(filter.to_string());
+        }
+        if let Some(stop_after) = self.stop_after {
+            let fade = FADE_OUT.min(stop_after);
+            filters.push(format!(
+                "afade=t=out:st={}:d={}",
+                (stop_after - fade).as_secs_f32(),
+                fade.as_secs_f32()
+            ));
+        }
+
+        if filters.is_empty() {
+            None
+        } else {
+            Some(filters.join(","))
+        }
+    }
+}
+
+/// Error returned for invalid transcode options
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    /// The filter chain isn't a simple comma separated list of filters
+    InvalidSyntax(String),
+    /// The filter chain uses a filter that isn't allowed
+    UnsupportedFilter(String),
+    /// The speed is out of the supported range
+    InvalidSpeed(f32),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::InvalidSyntax(chain) => write!(f, "Invalid filter chain `{}`", chain),
+            FilterError::UnsupportedFilter(name) => write!(f, "Unsupported filter `{}`", name),
+            FilterError::InvalidSpeed(speed) => write!(f, "Unsupported speed {}x", speed),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+static OPTIONS: RwLock<TranscodeOptions> = RwLock::new(TranscodeOptions::new());
+
+/// Validates `options` and applies them to the streams started from now on
+pub fn set_options(options: TranscodeOptions) -> Result<(), FilterError> {
+    if let Some(chain) = &options.audio_filter {
+        validate(chain)?;
+    }
+    if let Some(speed) = options.speed {
+        if !SPEED_RANGE.contains(&speed) {
+            return Err(FilterError::InvalidSpeed(speed));
+        }
+    }
+    *OPTIONS.write().unwrap() = options;
+
+    Ok(())
+}
+
+/// Returns the options applied to new streams
+pub fn options() -> TranscodeOptions {
+    OPTIONS.read().unwrap().clone()
+}
+
+/// Checks that `chain` is a plain chain of allowed filters, none of them
+/// given a file. Labels and multiple chains (`[a]`, `;`) aren't accepted.
+fn validate(chain: &str) -> Result<(), FilterError> {
+    if chain.trim().is_empty() || chain.contains(['[', ']', ';']) {
+        return Err(FilterError::InvalidSyntax(chain.into()));
+    }
+
+    for filter in chain.split(',') {
+        let name = filter.split('=').next().unwrap_or_default().trim();
+        if name.is_empty() {
+            return Err(FilterError::InvalidSyntax(chain.into()));
+        }
+        if !ALLOWED_FILTERS.contains(&name) {
+            return Err(FilterError::UnsupportedFilter(name.into()));
+        }
+        // Options follow the filter name, as `key=value` pairs separated by `:`
+        let options = filter
+            .split_once('=')
+            .map(|(_, options)| options)
+            .unwrap_or_default();
+        let file_option = options
+            .split(':')
+            .filter_map(|option| option.split('=').next())
+            .map(str::trim)
+            .find(|option| FILE_OPTIONS.contains(option));
+        if let Some(option) = file_option {
+            return Err(FilterError::UnsupportedFilter(format!(
+                "{} {}",
+                name, option
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds the `ffmpeg` command that transcodes the audio at `url` into
+/// `file_name`, reporting progress on stdout. Fails if the codec of `Best`
+/// can't be told from the extension of `file_name`.
+pub(crate) fn command(url: &str, file_name: &str) -> Result<Command, UnknownContainer> {
+    let options = options();
+
+    let mut command = binaries::ffmpeg();
+    // Progress is reported on stdout, see `stats::StatsReader`
+    command.args(["-progress", "pipe:1", "-nostats"]);
+    command.args(["-i", url]);
+    if let Some(chain) = options.filter_chain() {
+        command.args(["-af", &chain]);
+    }
+    let codec = options.codec(file_name)?;
+    command.args(["-c:a", codec]);
+    if let Some(bitrate) = options.bitrate {
+        if codec::has_bitrate(codec) {
+            command.args(["-b:a", &format!("{}k", bitrate)]);
+        }
+    }
+    if let Some(stop_after) = options.stop_after {
+        command.args(["-t", &stop_after.as_secs_f32().to_string()]);
+    }
+    command.arg(format!("file:{}", file_name));
+    command.arg("-y"); // If `file_name` exists, it's overwritten
+
+    Ok(command)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        validate, AudioFormat, FilterError, Normalization, TranscodeOptions, UnknownContainer,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn filter_validation() {
+        assert_eq!(validate("bass=g=3"), Ok(()));
+        assert_eq!(validate("equalizer=f=1000:t=q:w=1:g=2, crossfeed"), Ok(()));
+        assert_eq!(
+            validate("amovie=/etc/passwd"),
+            Err(FilterError::UnsupportedFilter("amovie".into()))
+        );
+        assert!(validate("firequalizer=dumpfile=/tmp/x").is_err());
+        assert_eq!(
+            validate("dynaudnorm=f=150:file=/tmp/x"),
+            Err(FilterError::UnsupportedFilter("dynaudnorm file".into()))
+        );
+        assert_eq!(
+            validate("[0:a]bass;[1:a]treble"),
+            Err(FilterError::InvalidSyntax("[0:a]bass;[1:a]treble".into()))
+        );
+        assert_eq!(
+            validate("bass,,treble"),
+            Err(FilterError::InvalidSyntax("bass,,treble".into()))
+        );
+    }
+
+    #[test]
+    fn speed_filters() {
+        let options = TranscodeOptions {
+            audio_filter: Some("bass=g=3".into()),
+            speed: Some(1.5),
+            ..Default::default()
+        };
+        assert_eq!(options.filter_chain().unwrap(), "bass=g=3,atempo=1.5");
+
+        let options = TranscodeOptions {
+            speed: Some(3.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.filter_chain().unwrap(),
+            "atempo=2,atempo=1.5",
+            "Speeds over 2x are split in several filters"
+        );
+
+        assert_eq!(TranscodeOptions::default().filter_chain(), None);
+    }
+
+    #[test]
+    fn normalization_profiles() {
+        let options = TranscodeOptions {
+            speed: Some(1.5),
+            normalization: Normalization::Music,
+            stop_after: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.filter_chain().unwrap(),
+            "atempo=1.5,loudnorm=I=-14:TP=-1:LRA=11,afade=t=out:st=50:d=10",
+            "The fade-out applies to the normalized stream"
+        );
+
+        let options = TranscodeOptions {
+            normalization: Normalization::Voice,
+            ..Default::default()
+        };
+        assert!(options.filter_chain().unwrap().starts_with("acompressor="));
+        assert_eq!(
+            options.codec("out.mp3"),
+            Ok("libmp3lame"),
+            "Normalized streams are re-encoded"
+        );
+    }
+
+    #[test]
+    fn codecs_fit_the_container() {
+        let best = TranscodeOptions {
+            format: AudioFormat::Best,
+            ..Default::default()
+        };
+        assert_eq!(best.codec("out.mp3"), Ok("libmp3lame"));
+        assert_eq!(best.codec("out.M4A"), Ok("aac"));
+        assert_eq!(best.codec("out.flac"), Ok("flac"));
+        assert_eq!(best.codec("out.wav"), Ok("pcm_s16le"));
+        assert_eq!(best.codec("out.opus"), Ok("libopus"));
+        assert_eq!(best.codec("out.ogg"), Ok("libopus"));
+        assert_eq!(best.codec("out.mka"), Ok("copy"));
+        for file_name in ["out.xyz", "out"] {
+            assert_eq!(
+                best.codec(file_name),
+                Err(UnknownContainer(file_name.into()))
+            );
+        }
+
+        let filtered = TranscodeOptions {
+            audio_filter: Some("bass=g=3".into()),
+            ..best
+        };
+        assert_eq!(filtered.codec("out.mka"), Ok("libopus"));
+        assert_eq!(
+            TranscodeOptions::default().codec("out.mka"),
+            Ok("libmp3lame"),
+            "Explicit formats are kept"
+        );
+        assert_eq!(TranscodeOptions::default().codec("out"), Ok("libmp3lame"));
+    }
+
+    #[test]
+    fn sleep_timer_fade_out() {
+        let options = TranscodeOptions {
+            stop_after: Some(Duration::from_secs(1800)),
+            ..Default::default()
+        };
+        assert_eq!(options.filter_chain().unwrap(), "afade=t=out:st=1790:d=10");
+
+        let options = TranscodeOptions {
+            stop_after: Some(Duration::from_secs(4)),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.filter_chain().unwrap(),
+            "afade=t=out:st=0:d=4",
+            "The fade-out never starts before the stream"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Waveform peaks of downloaded and streamed files, to draw seek bars.
+//!
+//! Needs the `waveform` feature. Files are decoded with Symphonia, so mp3, m4a
+//! and flac files are supported, but not opus. Peaks are saved in a cache
+//! directory, see [`set_cache_dir`], under a hash of the path of the file
+//! they're computed from, and read back from there while the file doesn't
+//! change. The directory of the file is left untouched.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as DecodeError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// Frames whose peak is kept while decoding, before the peaks are grouped
+/// into buckets
+const BLOCK_FRAMES: usize = 1024;
+
+static CACHE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Saves peaks in `dir`, or with `None` in `phosphorus-peaks` in the temporary
+/// directory, the default
+pub fn set_cache_dir(dir: Option<PathBuf>) {
+    *CACHE_DIR.write().unwrap() = dir;
+}
+
+/// Returns the directory peaks are saved in
+pub fn cache_dir() -> PathBuf {
+    match &*CACHE_DIR.read().unwrap() {
+        Some(dir) => dir.clone(),
+        None => std::env::temp_dir().join("phosphorus-peaks"),
+    }
+}
+
+/// Waveform of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Peaks {
+    /// Highest amplitude of each slice of the file, from 0 to 1
+    pub buckets: Vec<f32>,
+    /// Size of the file the peaks were computed from
+    source_size: u64,
+}
+
+/// Returns `buckets` peaks of the audio file at `path`, saved from an earlier
+/// call or computed now
+pub fn peaks(path: &Path, buckets: usize) -> Result<Peaks, Box<dyn Error>> {
+    let source_size = fs::metadata(path)?.len();
+    let saved_path = saved_peaks_path(&cache_dir(), path);
+    if let Ok(saved) = fs::read_to_string(&saved_path) {
+        if let Ok(saved) = serde_json::from_str::<Peaks>(&saved) {
+            if saved.source_size == source_size && saved.buckets.len() == buckets {
+                return Ok(saved);
+            }
+        }
+    }
+
+    let peaks = Peaks {
+        buckets: group(&block_peaks(path)?, buckets),
+        source_size,
+    };
+    // Failing to save only costs a decode next time
+    let text = serde_json::to_string(&peaks)?;
+    if let Some(dir) = saved_path.parent() {
+        let _ = fs::create_dir_all(dir).and_then(|_| fs::write(&saved_path, text));
+    }
+
+    Ok(peaks)
+}
+
+/// Path the peaks of the file at `path` are saved at in `dir`, named after the
+/// FNV-1a hash of its absolute path
+fn saved_peaks_path(dir: &Path, path: &Path) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let hash = path
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    dir.join(format!("{:016x}.json", hash))
+}
+
+/// Decodes the file at `path`, returning the peak of each block of frames
+fn block_peaks(path: &Path) -> Result<Vec<f32>, Box<dyn Error>> {
+    let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+    let track = format.default_track().ok_or("No audio track")?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut peaks = vec![];
+    let (mut block_peak, mut block_frames) = (0f32, 0);
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupted packet is skipped, like players do
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let spec = *decoded.spec();
+        let samples =
+            samples.get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, spec));
+        samples.copy_interleaved_ref(decoded);
+        for frame in samples.samples().chunks(spec.channels.count()) {
+            for sample in frame {
+                block_peak = block_peak.max(sample.abs());
+            }
+            block_frames += 1;
+            if block_frames == BLOCK_FRAMES {
+                peaks.push(block_peak.min(1.0));
+                block_peak = 0.0;
+                block_frames = 0;
+            }
+        }
+    }
+    if block_frames > 0 {
+        peaks.push(block_peak.min(1.0));
+    }
+
+    Ok(peaks)
+}
+
+/// Groups `peaks` into `buckets` slices, keeping the highest of each
+fn group(peaks: &[f32], buckets: usize) -> Vec<f32> {
+    if peaks.is_empty() || buckets == 0 {
+        return vec![0.0; buckets];
+    }
+
+    (0..buckets)
+        .map(|bucket| {
+            let start = bucket * peaks.len() / buckets;
+            let end = ((bucket + 1) * peaks.len() / buckets).max(start + 1);
+            peaks[start..end.min(peaks.len())]
+                .iter()
+                .cloned()
+                .fold(0.0, f32::max)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cache_dir, group, peaks, saved_peaks_path};
+    use std::fs;
+
+    /// Encodes 16-bit mono `samples` as a WAV file
+    fn wav(samples: &[i16]) -> Vec<u8> {
+        let data_size = (samples.len() * 2) as u32;
+        let mut wav = vec![];
+        wav.extend(b"RIFF");
+        wav.extend((36 + data_size).to_le_bytes());
+        wav.extend(b"WAVEfmt ");
+        wav.extend(16u32.to_le_bytes());
+        wav.extend(1u16.to_le_bytes()); // PCM
+        wav.extend(1u16.to_le_bytes()); // Mono
+        wav.extend(8000u32.to_le_bytes());
+        wav.extend(16000u32.to_le_bytes());
+        wav.extend(2u16.to_le_bytes());
+        wav.extend(16u16.to_le_bytes());
+        wav.extend(b"data");
+        wav.extend(data_size.to_le_bytes());
+        for sample in samples {
+            wav.extend(sample.to_le_bytes());
+        }
+        wav
+    }
+
+    #[test]
+    fn wav_peaks() {
+        // A silent half, then a loud one
+        let mut samples = vec![0i16; 4096];
+        samples.extend((0..4096).map(|i| if i % 2 == 0 { 16384 } else { -16384 }));
+        let path = std::env::temp_dir().join(format!("phosphorus-{}.wav", std::process::id()));
+        fs::write(&path, wav(&samples)).unwrap();
+
+        let computed = peaks(&path, 4).unwrap();
+        assert_eq!(computed.buckets, vec![0.0, 0.0, 0.5, 0.5]);
+        let saved = saved_peaks_path(&cache_dir(), &path);
+        assert!(saved.exists());
+        assert_ne!(saved.parent(), path.parent(), "Not next to the file");
+        assert_eq!(peaks(&path, 4).unwrap(), computed, "Read back");
+
+        let other = path.with_file_name("other.wav");
+        assert_ne!(saved_peaks_path(&cache_dir(), &other), saved);
+
+        let _ = fs::remove_file(saved);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn grouping() {
+        assert_eq!(group(&[0.1, 0.4, 0.2, 0.8, 0.3], 2), vec![0.4, 0.8]);
+        assert_eq!(group(&[0.5], 3), vec![0.5, 0.5, 0.5]);
+        assert_eq!(group(&[], 2), vec![0.0, 0.0]);
+    }
+}